@@ -0,0 +1,163 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, AddAssign};
+
+// Just enough of an unsigned big integer to count things that outgrow u64
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BigUint {
+    // Base 2^32, least significant first, without trailing zeros
+    limbs: Vec<u32>,
+}
+
+impl BigUint {
+    pub fn zero() -> Self {
+        BigUint { limbs: Vec::new() }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn to_u64(&self) -> Option<u64> {
+        match self.limbs[..] {
+            [] => Some(0),
+            [low] => Some(low as u64),
+            [low, high] => Some((high as u64) << 32 | low as u64),
+            _ => None,
+        }
+    }
+
+    fn trim(&mut self) {
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+    }
+
+    // Divides in place, returning the remainder
+    fn div_rem_small(&mut self, divisor: u32) -> u32 {
+        let mut rem = 0u64;
+        for limb in self.limbs.iter_mut().rev() {
+            let cur = rem << 32 | *limb as u64;
+            *limb = (cur / divisor as u64) as u32;
+            rem = cur % divisor as u64;
+        }
+        self.trim();
+        rem as u32
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(n: u64) -> Self {
+        let mut big = BigUint {
+            limbs: vec![n as u32, (n >> 32) as u32],
+        };
+        big.trim();
+        big
+    }
+}
+
+impl AddAssign<&BigUint> for BigUint {
+    fn add_assign(&mut self, other: &BigUint) {
+        if self.limbs.len() < other.limbs.len() {
+            self.limbs.resize(other.limbs.len(), 0);
+        }
+        let mut carry = 0u64;
+        for (i, limb) in self.limbs.iter_mut().enumerate() {
+            let sum = *limb as u64 + other.limbs.get(i).copied().unwrap_or(0) as u64 + carry;
+            *limb = sum as u32;
+            carry = sum >> 32;
+        }
+        if carry > 0 {
+            self.limbs.push(carry as u32);
+        }
+    }
+}
+
+impl Add<&BigUint> for BigUint {
+    type Output = BigUint;
+
+    fn add(mut self, other: &BigUint) -> BigUint {
+        self += other;
+        self
+    }
+}
+
+impl Ord for BigUint {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.limbs
+            .len()
+            .cmp(&other.limbs.len())
+            .then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+}
+
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        // Peel off nine decimal digits at a time
+        let mut rest = self.clone();
+        let mut chunks = Vec::new();
+        while !rest.is_zero() {
+            chunks.push(rest.div_rem_small(1_000_000_000));
+        }
+        let mut chunks = chunks.iter().rev();
+        write!(f, "{}", chunks.next().unwrap())?;
+        for chunk in chunks {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_and_to_u64() {
+        for &n in [0, 1, u32::MAX as u64, 1 << 32, u64::MAX].iter() {
+            assert_eq!(BigUint::from(n).to_u64(), Some(n));
+        }
+        assert!(BigUint::from(0).is_zero());
+        assert_eq!(BigUint::from(0), BigUint::zero());
+    }
+
+    #[test]
+    fn add_carries() {
+        let sum = BigUint::from(u64::MAX) + &BigUint::from(1);
+        assert_eq!(sum.to_u64(), None);
+        assert_eq!(sum.to_string(), "18446744073709551616");
+        let mut acc = BigUint::from(5);
+        acc += &BigUint::zero();
+        assert_eq!(acc, BigUint::from(5));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(BigUint::zero().to_string(), "0");
+        assert_eq!(BigUint::from(1_000_000_000).to_string(), "1000000000");
+        assert_eq!(BigUint::from(u64::MAX).to_string(), "18446744073709551615");
+        // 2^128
+        let mut big = BigUint::from(1);
+        for _ in 0..128 {
+            big = big.clone() + &big;
+        }
+        assert_eq!(big.to_string(), "340282366920938463463374607431768211456");
+    }
+
+    #[test]
+    fn ordering() {
+        let big = BigUint::from(u64::MAX) + &BigUint::from(1);
+        assert!(big > BigUint::from(u64::MAX));
+        assert!(BigUint::from(3) < BigUint::from(1 << 32));
+        assert!(BigUint::from(7) > BigUint::zero());
+    }
+}
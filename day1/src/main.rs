@@ -1,34 +1,412 @@
-use std::collections::BTreeSet;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+const TARGET: i64 = 2020;
+
+fn find_k_sum(values: &[i64], k: usize, target: i64) -> Option<Vec<i64>> {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    find_k_sum_sorted(&sorted, k, i128::from(target))
+}
+
+// Each entry can be used once, so repeated values only match when they are repeated in the input.
+// Sums are taken as i128 so extreme values cannot overflow
+fn find_k_sum_sorted(sorted: &[i64], k: usize, target: i128) -> Option<Vec<i64>> {
+    match k {
+        0 if target == 0 => Some(Vec::new()),
+        0 => None,
+        1 => {
+            let target = i64::try_from(target).ok()?;
+            sorted.binary_search(&target).ok().map(|i| vec![sorted[i]])
+        }
+        2 => {
+            if sorted.is_empty() {
+                return None;
+            }
+            let (mut low, mut high) = (0, sorted.len() - 1);
+            while low < high {
+                let sum = i128::from(sorted[low]) + i128::from(sorted[high]);
+                if sum == target {
+                    return Some(vec![sorted[low], sorted[high]]);
+                } else if sum < target {
+                    low += 1;
+                } else {
+                    high -= 1;
+                }
+            }
+            None
+        }
+        _ => sorted.iter().enumerate().find_map(|(i, &first)| {
+            if i > 0 && sorted[i - 1] == first {
+                return None;
+            }
+            let mut rest =
+                find_k_sum_sorted(&sorted[(i + 1)..], k - 1, target - i128::from(first))?;
+            rest.insert(0, first);
+            Some(rest)
+        }),
+    }
+}
+
+// Yields every distinct combination of values summing to the target in lexicographic order,
+// along with how many ways it can be picked from the entries
+struct KSums {
+    // Sorted distinct values and how many entries have each value
+    distinct: Vec<(i64, usize)>,
+    k: usize,
+    target: i64,
+    // Indices into distinct for all but the last two picks, non-decreasing
+    prefix: Vec<usize>,
+    pending: VecDeque<Vec<usize>>,
+    done: bool,
+}
+
+impl KSums {
+    fn new(values: &[i64], k: usize, target: i64) -> Self {
+        let mut sorted = values.to_vec();
+        sorted.sort_unstable();
+        let mut distinct: Vec<(i64, usize)> = Vec::new();
+        for v in sorted {
+            match distinct.last_mut() {
+                Some((last, count)) if *last == v => *count += 1,
+                _ => distinct.push((v, 1)),
+            }
+        }
+        let mut sums = KSums {
+            distinct,
+            k,
+            target,
+            prefix: Vec::new(),
+            pending: VecDeque::new(),
+            done: false,
+        };
+        if sums.fill_prefix() {
+            sums.find_tails();
+        } else {
+            sums.done = true;
+        }
+        sums
+    }
+
+    fn prefix_len(&self) -> usize {
+        self.k.saturating_sub(2)
+    }
+
+    fn available(&self, index: usize, picked: &[usize]) -> usize {
+        let used = picked.iter().filter(|&&i| i == index).count();
+        self.distinct[index].1 - used
+    }
+
+    // Extends the prefix with the smallest valid picks
+    fn fill_prefix(&mut self) -> bool {
+        while self.prefix.len() < self.prefix_len() {
+            let start = self.prefix.last().copied().unwrap_or(0);
+            let next = if start < self.distinct.len() && self.available(start, &self.prefix) > 0 {
+                start
+            } else {
+                start + 1
+            };
+            if next >= self.distinct.len() {
+                return false;
+            }
+            self.prefix.push(next);
+        }
+        true
+    }
+
+    fn advance_prefix(&mut self) -> bool {
+        let depth = self.prefix_len();
+        while let Some(last) = self.prefix.pop() {
+            if last + 1 < self.distinct.len() {
+                self.prefix.push(last + 1);
+                if self.fill_prefix() {
+                    return true;
+                }
+                self.prefix.truncate(depth);
+            }
+        }
+        false
+    }
+
+    // Queues every way to finish the current prefix
+    fn find_tails(&mut self) {
+        let picked: i128 = self
+            .prefix
+            .iter()
+            .map(|&i| i128::from(self.distinct[i].0))
+            .sum();
+        let remaining = i128::from(self.target) - picked;
+        let start = self.prefix.last().copied().unwrap_or(0);
+        let candidates = start..self.distinct.len();
+        let usable = |i: usize| self.available(i, &self.prefix) > 0;
+        let mut tails = Vec::new();
+        match self.k {
+            0 if remaining == 0 => tails.push(Vec::new()),
+            0 => {}
+            1 => {
+                if let Ok(remaining) = i64::try_from(remaining) {
+                    if let Ok(i) = self.distinct.binary_search_by_key(&remaining, |&(v, _)| v) {
+                        tails.push(vec![i]);
+                    }
+                }
+            }
+            _ if candidates.is_empty() => {}
+            _ => {
+                let (mut low, mut high) = (start, self.distinct.len() - 1);
+                while low <= high {
+                    let sum = i128::from(self.distinct[low].0) + i128::from(self.distinct[high].0);
+                    if sum < remaining {
+                        low += 1;
+                    } else if sum > remaining {
+                        if high == 0 {
+                            break;
+                        }
+                        high -= 1;
+                    } else {
+                        let fits = if low == high {
+                            self.available(low, &self.prefix) >= 2
+                        } else {
+                            usable(low) && usable(high)
+                        };
+                        if fits {
+                            tails.push(vec![low, high]);
+                        }
+                        low += 1;
+                        if high == 0 {
+                            break;
+                        }
+                        high -= 1;
+                    }
+                }
+            }
+        }
+        for tail in tails {
+            let mut picks = self.prefix.clone();
+            picks.extend(tail);
+            self.pending.push_back(picks);
+        }
+    }
+
+    fn ways(&self, picks: &[usize]) -> u64 {
+        let mut ways = 1;
+        let mut i = 0;
+        while i < picks.len() {
+            let used = picks[i..].iter().take_while(|&&p| p == picks[i]).count();
+            ways *= binomial(self.distinct[picks[i]].1 as u64, used as u64);
+            i += used;
+        }
+        ways
+    }
+}
+
+impl Iterator for KSums {
+    type Item = (Vec<i64>, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() && !self.done {
+            if self.advance_prefix() {
+                self.find_tails();
+            } else {
+                self.done = true;
+            }
+        }
+        let picks = self.pending.pop_front()?;
+        let combination = picks.iter().map(|&i| self.distinct[i].0).collect();
+        Some((combination, self.ways(&picks)))
+    }
+}
+
+fn binomial(n: u64, k: u64) -> u64 {
+    (0..k).fold(1, |acc, i| acc * (n - i) / (i + 1))
+}
+
+fn k_sums(values: &[i64], k: usize, target: i64) -> KSums {
+    KSums::new(values, k, target)
+}
+
+// Number of ways to pick k entries summing to the target, counting equal values separately
+fn count_k_sums(values: &[i64], k: usize, target: i64) -> u64 {
+    k_sums(values, k, target).map(|(_, ways)| ways).sum()
+}
 
 fn main() {
-    let input = include_str!("input")
+    let values = include_str!("input")
         .lines()
-        .filter_map(|l| l.parse::<u32>().ok());
-
-    let mut seen = BTreeSet::new();
-
-    let (mut part1, mut part2) = (false, false);
-    for n in input {
-        let remainder = 2020 - n;
-        if !part1 {
-            if seen.contains(&remainder) {
-                println!("Part 1: Multiple is {}", n * remainder);
-                part1 = true;
-            }
-        }
-        if !part2 {
-            for previous in seen.range(1..remainder) {
-                let second_remainder = remainder - previous;
-                if seen.contains(&second_remainder) {
-                    println!("Part 2: Multiple is {}", n * previous * second_remainder);
-                    part2 = true;
-                    break;
+        .filter_map(|l| l.parse::<i64>().ok())
+        .collect::<Vec<_>>();
+
+    for (part, k) in [(1, 2), (2, 3)].iter() {
+        match find_k_sum(&values, *k, TARGET) {
+            Some(entries) => println!(
+                "Part {}: Multiple of {:?} is {}",
+                part,
+                entries,
+                entries.iter().product::<i64>()
+            ),
+            None => println!("Part {}: No {} entries sum to {}", part, k, TARGET),
+        }
+        let combinations = k_sums(&values, *k, TARGET).count();
+        let ways = count_k_sums(&values, *k, TARGET);
+        if ways > 1 {
+            println!(
+                "Part {}: Answer is ambiguous, {} combinations picked {} ways",
+                part, combinations, ways
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EXAMPLE: [i64; 6] = [1721, 979, 366, 299, 675, 1456];
+
+    #[test]
+    fn two_sum() {
+        assert_eq!(find_k_sum(&EXAMPLE, 2, 2020), Some(vec![299, 1721]));
+        assert_eq!(find_k_sum(&EXAMPLE, 2, 1), None);
+    }
+
+    #[test]
+    fn three_sum() {
+        assert_eq!(find_k_sum(&EXAMPLE, 3, 2020), Some(vec![366, 675, 979]));
+    }
+
+    #[test]
+    fn k_sum_other_sizes() {
+        assert_eq!(find_k_sum(&EXAMPLE, 1, 366), Some(vec![366]));
+        assert_eq!(find_k_sum(&EXAMPLE, 0, 0), Some(vec![]));
+        assert_eq!(
+            find_k_sum(&EXAMPLE, 4, 1721 + 979 + 366 + 299),
+            Some(vec![299, 366, 979, 1721])
+        );
+        assert_eq!(find_k_sum(&EXAMPLE, 7, 2020), None);
+    }
+
+    #[test]
+    fn k_sum_duplicates() {
+        assert_eq!(find_k_sum(&[1010, 5], 2, 2020), None);
+        assert_eq!(
+            find_k_sum(&[5, 1010, 1010], 2, 2020),
+            Some(vec![1010, 1010])
+        );
+        assert_eq!(find_k_sum(&[7, 7, 7], 3, 21), Some(vec![7, 7, 7]));
+        assert_eq!(find_k_sum(&[7, 7], 3, 21), None);
+    }
+
+    #[test]
+    fn k_sum_negative() {
+        assert_eq!(find_k_sum(&[-5, 3, 10, -2], 2, 1), Some(vec![-2, 3]));
+        assert_eq!(find_k_sum(&[-5, 3, 10, -2], 3, 3), Some(vec![-5, -2, 10]));
+    }
+
+    #[test]
+    fn k_sum_extreme_values() {
+        assert_eq!(find_k_sum(&[i64::MAX, 1, 2], 3, -5), None);
+        assert_eq!(find_k_sum(&[i64::MAX, i64::MAX], 2, -2), None);
+        assert_eq!(
+            find_k_sum(&[i64::MIN, i64::MAX, 5], 2, -1),
+            Some(vec![i64::MIN, i64::MAX])
+        );
+        assert_eq!(
+            find_k_sum(&[i64::MIN, -1, i64::MAX, 1], 3, i64::MIN),
+            Some(vec![i64::MIN, -1, 1])
+        );
+    }
+
+    #[test]
+    fn k_sums_all_combinations() {
+        let values = [1, 2, 3, 4, 5, 6];
+        let res: Vec<_> = k_sums(&values, 3, 10).collect();
+        assert_eq!(
+            res,
+            vec![(vec![1, 3, 6], 1), (vec![1, 4, 5], 1), (vec![2, 3, 5], 1)]
+        );
+        let res: Vec<_> = k_sums(&values, 2, 7).map(|(c, _)| c).collect();
+        assert_eq!(res, vec![vec![1, 6], vec![2, 5], vec![3, 4]]);
+    }
+
+    #[test]
+    fn k_sums_example_is_unique() {
+        assert_eq!(count_k_sums(&EXAMPLE, 2, 2020), 1);
+        assert_eq!(count_k_sums(&EXAMPLE, 3, 2020), 1);
+        assert_eq!(count_k_sums(&EXAMPLE, 2, 1), 0);
+    }
+
+    #[test]
+    fn k_sums_duplicates() {
+        let values = [1010, 1010, 1010, 5, 2015];
+        let res: Vec<_> = k_sums(&values, 2, 2020).collect();
+        assert_eq!(res, vec![(vec![5, 2015], 1), (vec![1010, 1010], 3)]);
+        assert_eq!(count_k_sums(&values, 2, 2020), 4);
+        let res: Vec<_> = k_sums(&[2, 2, 2, 2], 3, 6).collect();
+        assert_eq!(res, vec![(vec![2, 2, 2], 4)]);
+        assert_eq!(k_sums(&[2, 2], 3, 6).count(), 0);
+    }
+
+    #[test]
+    fn k_sums_small_k() {
+        assert_eq!(
+            k_sums(&EXAMPLE, 0, 0).collect::<Vec<_>>(),
+            vec![(vec![], 1)]
+        );
+        assert_eq!(k_sums(&EXAMPLE, 0, 1).count(), 0);
+        assert_eq!(
+            k_sums(&[3, 3, 4], 1, 3).collect::<Vec<_>>(),
+            vec![(vec![3], 2)]
+        );
+        assert_eq!(k_sums(&[], 2, 0).count(), 0);
+    }
+
+    #[test]
+    fn k_sums_extreme_values() {
+        assert_eq!(k_sums(&[i64::MAX, 1, 2], 2, 0).count(), 0);
+        assert_eq!(k_sums(&[i64::MAX, i64::MAX, 1], 3, 1).count(), 0);
+        assert_eq!(k_sums(&[i64::MIN, 0, 1], 1, i64::MAX).count(), 0);
+        assert_eq!(
+            k_sums(&[i64::MIN, i64::MIN, i64::MAX, i64::MAX, 0], 4, -2).collect::<Vec<_>>(),
+            vec![(vec![i64::MIN, i64::MIN, i64::MAX, i64::MAX], 1)]
+        );
+        assert_eq!(
+            k_sums(&[i64::MIN, -1, i64::MAX, 0, 1], 3, -1).collect::<Vec<_>>(),
+            vec![(vec![i64::MIN, 0, i64::MAX], 1)]
+        );
+    }
+
+    #[test]
+    fn k_sums_matches_brute_force() {
+        let values = [-3, 0, 0, 1, 2, 2, 2, 5, 7, -1];
+        for k in 0..=5 {
+            for target in -6..=15 {
+                let mut expected = 0;
+                for mask in 0u32..(1 << values.len()) {
+                    if mask.count_ones() as usize != k {
+                        continue;
+                    }
+                    let sum: i64 = (0..values.len())
+                        .filter(|i| mask & (1 << i) != 0)
+                        .map(|i| values[i])
+                        .sum();
+                    if sum == target {
+                        expected += 1;
+                    }
                 }
+                assert_eq!(
+                    count_k_sums(&values, k, target),
+                    expected,
+                    "k={} target={}",
+                    k,
+                    target
+                );
+                let first = k_sums(&values, k, target).next().map(|(c, _)| c);
+                assert_eq!(first, find_k_sum(&values, k, target));
+                let combinations: Vec<_> = k_sums(&values, k, target).map(|(c, _)| c).collect();
+                let mut sorted = combinations.clone();
+                sorted.sort();
+                sorted.dedup();
+                assert_eq!(combinations, sorted);
             }
         }
-        if part1 && part2 {
-            break;
-        }
-        seen.insert(n);
     }
 }
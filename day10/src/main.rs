@@ -1,46 +1,452 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bignum::BigUint;
 use parser::{endline_terminated, many1, unsigned_number};
 
-fn count_paths(sorted_numbers: &[u64]) -> u64 {
-    let mut trail = [0; 3];
-    for (i, n) in sorted_numbers.iter().enumerate().rev() {
-        let mut branches = 0;
-        for (j, next) in sorted_numbers[(i + 1)..].iter().take(3).enumerate() {
-            if (1..=3).contains(&(next - n)) {
-                branches += trail[j];
-            } else {
+// Index of the adapter whose arrangement count no longer fit
+#[derive(Debug, PartialEq)]
+struct Overflow {
+    index: usize,
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "arrangement count overflowed at adapter {}", self.index)
+    }
+}
+
+impl Error for Overflow {}
+
+// Why a set of adapters can't be chained from the outlet to the device
+#[derive(Debug, PartialEq)]
+enum ChainError {
+    Duplicate { rating: u64 },
+    Gap { from: u64, to: u64 },
+    Unreachable { stuck_at: u64 },
+    RatingTooLarge { rating: u64 },
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::Duplicate { rating } => write!(f, "more than one adapter rated {}", rating),
+            ChainError::Gap { from, to } => {
+                write!(f, "chain breaks between {} and {}", from, to)
+            }
+            ChainError::Unreachable { stuck_at } => {
+                write!(f, "no allowed step after adapter {}", stuck_at)
+            }
+            ChainError::RatingTooLarge { rating } => {
+                write!(f, "no room for a device above {}", rating)
+            }
+        }
+    }
+}
+
+impl Error for ChainError {}
+
+trait Count: Clone {
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+}
+
+impl Count for u64 {
+    fn zero() -> Self {
+        0
+    }
+
+    fn one() -> Self {
+        1
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        u64::checked_add(*self, *other)
+    }
+}
+
+impl Count for BigUint {
+    fn zero() -> Self {
+        BigUint::zero()
+    }
+
+    fn one() -> Self {
+        BigUint::from(1)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self.clone() + other)
+    }
+}
+
+// The differences allowed between one adapter and the next
+#[derive(Debug, PartialEq, Clone)]
+struct JoltageRules {
+    gaps: BTreeSet<u64>,
+}
+
+impl JoltageRules {
+    fn new(gaps: impl IntoIterator<Item = u64>) -> Option<Self> {
+        let gaps: BTreeSet<u64> = gaps.into_iter().collect();
+        if gaps.is_empty() || gaps.contains(&0) {
+            return None;
+        }
+        Some(JoltageRules { gaps })
+    }
+
+    // Comma separated gaps, like "1,2,5"
+    fn parse(spec: &str) -> Option<Self> {
+        let gaps = spec
+            .split(',')
+            .map(|gap| gap.trim().parse().ok())
+            .collect::<Option<Vec<u64>>>()?;
+        JoltageRules::new(gaps)
+    }
+
+    fn max_gap(&self) -> u64 {
+        *self.gaps.iter().next_back().unwrap()
+    }
+
+    fn allows(&self, gap: u64) -> bool {
+        self.gaps.contains(&gap)
+    }
+}
+
+impl Default for JoltageRules {
+    fn default() -> Self {
+        JoltageRules::new(1..=3).unwrap()
+    }
+}
+
+// Number of ways to reach the last adapter from each adapter
+fn path_counts<C: Count>(sorted_numbers: &[u64], rules: &JoltageRules) -> Result<Vec<C>, Overflow> {
+    let mut counts = vec![C::zero(); sorted_numbers.len()];
+    for (i, &n) in sorted_numbers.iter().enumerate().rev() {
+        if i + 1 == sorted_numbers.len() {
+            counts[i] = C::one();
+            continue;
+        }
+        let mut branches = C::zero();
+        for (j, &next) in sorted_numbers.iter().enumerate().skip(i + 1) {
+            let gap = next - n;
+            if gap > rules.max_gap() {
                 break;
             }
+            if rules.allows(gap) {
+                branches = branches
+                    .checked_add(&counts[j])
+                    .ok_or(Overflow { index: i })?;
+            }
         }
-        trail = [std::cmp::max(branches, 1), trail[0], trail[1]];
+        counts[i] = branches;
     }
-    trail[0]
+    Ok(counts)
 }
 
-fn main() {
-    let input = include_str!("input");
-    let (mut numbers, rest) = many1(|inp| endline_terminated(unsigned_number, inp), input)
-        .expect("Failed to parse input");
-    assert!(rest.is_empty());
+fn count_paths_with<C: Count>(sorted_numbers: &[u64], rules: &JoltageRules) -> Result<C, Overflow> {
+    let counts = path_counts(sorted_numbers, rules)?;
+    Ok(counts.into_iter().next().unwrap_or_else(C::zero))
+}
 
+fn count_paths(sorted_numbers: &[u64], rules: &JoltageRules) -> Result<u64, Overflow> {
+    count_paths_with(sorted_numbers, rules)
+}
+
+// Never overflows, for chains too long to count in a u64
+fn count_paths_big(sorted_numbers: &[u64], rules: &JoltageRules) -> BigUint {
+    count_paths_with(sorted_numbers, rules).unwrap()
+}
+
+// How often each difference occurs between consecutive adapters
+fn gap_histogram(sorted_numbers: &[u64]) -> BTreeMap<u64, usize> {
+    let mut histogram = BTreeMap::new();
+    for win in sorted_numbers.windows(2) {
+        *histogram.entry(win[1] - win[0]).or_insert(0) += 1;
+    }
+    histogram
+}
+
+// Sorts the adapters between the outlet and the device, the largest allowed gap above the
+// highest adapter, and checks that at least one arrangement connects them
+fn adapter_chain(mut numbers: Vec<u64>, rules: &JoltageRules) -> Result<Vec<u64>, ChainError> {
     numbers.push(0);
     numbers.sort_unstable();
     let max = *numbers.last().unwrap();
-    numbers.push(max + 3);
-
-    let (ones, threes) = numbers
-        .windows(2)
-        .map(|win| win[1] - win[0])
-        .fold((0, 0), |(o, t), n| {
-            if n == 1 {
-                (o + 1, t)
-            } else if n == 3 {
-                (o, t + 1)
+    let device = max
+        .checked_add(rules.max_gap())
+        .ok_or(ChainError::RatingTooLarge { rating: max })?;
+    numbers.push(device);
+
+    for win in numbers.windows(2) {
+        let (from, to) = (win[0], win[1]);
+        if from == to {
+            return Err(ChainError::Duplicate { rating: from });
+        }
+        if to - from > rules.max_gap() {
+            return Err(ChainError::Gap { from, to });
+        }
+    }
+
+    // Gaps inside the window may still be disallowed, so walk forward from the outlet
+    let mut reachable = vec![false; numbers.len()];
+    reachable[0] = true;
+    let mut furthest = 0;
+    for i in 0..numbers.len() {
+        if !reachable[i] {
+            continue;
+        }
+        furthest = i;
+        for j in next_steps(&numbers, rules, i) {
+            reachable[j] = true;
+        }
+    }
+    if furthest + 1 < numbers.len() {
+        return Err(ChainError::Unreachable {
+            stuck_at: numbers[furthest],
+        });
+    }
+    Ok(numbers)
+}
+
+// Indices of the adapters that can directly follow the one at from, in order
+fn next_steps<'a>(
+    sorted_numbers: &'a [u64],
+    rules: &'a JoltageRules,
+    from: usize,
+) -> impl Iterator<Item = usize> + 'a {
+    let n = sorted_numbers[from];
+    (from + 1..sorted_numbers.len())
+        .take_while(move |&j| sorted_numbers[j] - n <= rules.max_gap())
+        .filter(move |&j| rules.allows(sorted_numbers[j] - n))
+}
+
+// Every arrangement from the first adapter to the last, in lexicographic order
+struct Arrangements<'a> {
+    sorted_numbers: &'a [u64],
+    rules: &'a JoltageRules,
+    // Whether the last adapter can be reached from each adapter, so dead ends are never explored
+    reaches_end: Vec<bool>,
+    path: Vec<usize>,
+    started: bool,
+}
+
+impl<'a> Arrangements<'a> {
+    fn new(sorted_numbers: &'a [u64], rules: &'a JoltageRules) -> Self {
+        let mut reaches_end = vec![false; sorted_numbers.len()];
+        for i in (0..sorted_numbers.len()).rev() {
+            reaches_end[i] = i + 1 == sorted_numbers.len()
+                || next_steps(sorted_numbers, rules, i).any(|j| reaches_end[j]);
+        }
+        let path = if reaches_end.first() == Some(&true) {
+            vec![0]
+        } else {
+            Vec::new()
+        };
+        Arrangements {
+            sorted_numbers,
+            rules,
+            reaches_end,
+            path,
+            started: false,
+        }
+    }
+
+    fn next_step(&self, from: usize, after: usize) -> Option<usize> {
+        next_steps(self.sorted_numbers, self.rules, from)
+            .find(|&j| j > after && self.reaches_end[j])
+    }
+
+    // Follows the first step from the end of the path until it reaches the last adapter
+    fn descend(&mut self) {
+        while let Some(&last) = self.path.last() {
+            if last + 1 == self.sorted_numbers.len() {
+                break;
+            }
+            let step = self.next_step(last, last).unwrap();
+            self.path.push(step);
+        }
+    }
+
+    fn current(&self) -> Vec<u64> {
+        self.path.iter().map(|&i| self.sorted_numbers[i]).collect()
+    }
+}
+
+impl<'a> Iterator for Arrangements<'a> {
+    type Item = Vec<u64>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            self.descend();
+            return if self.path.is_empty() {
+                None
             } else {
-                (o, t)
+                Some(self.current())
+            };
+        }
+        // Backtrack to the deepest adapter with an untried step
+        while let Some(child) = self.path.pop() {
+            let parent = *self.path.last()?;
+            if let Some(step) = self.next_step(parent, child) {
+                self.path.push(step);
+                self.descend();
+                return Some(self.current());
             }
-        });
-    println!("Part 1: Multiple of ones and threes: {}", ones * threes);
-    println!("Part 2: Number of alternatives: {}", count_paths(&numbers));
+        }
+        None
+    }
+}
+
+fn arrangements<'a>(sorted_numbers: &'a [u64], rules: &'a JoltageRules) -> Arrangements<'a> {
+    Arrangements::new(sorted_numbers, rules)
+}
+
+// The k-th arrangement in lexicographic order, counting from zero
+fn kth_arrangement(
+    sorted_numbers: &[u64],
+    rules: &JoltageRules,
+    mut k: u64,
+) -> Result<Option<Vec<u64>>, Overflow> {
+    let counts: Vec<u64> = path_counts(sorted_numbers, rules)?;
+    if counts.first().is_none_or(|&total| k >= total) {
+        return Ok(None);
+    }
+    let mut i = 0;
+    let mut arrangement = vec![sorted_numbers[0]];
+    while i + 1 < sorted_numbers.len() {
+        for j in next_steps(sorted_numbers, rules, i) {
+            if k < counts[j] {
+                i = j;
+                break;
+            }
+            k -= counts[j];
+        }
+        arrangement.push(sorted_numbers[i]);
+    }
+    Ok(Some(arrangement))
+}
+
+// Picks each arrangement with equal probability, given a source of random u64s
+fn sample_arrangement(
+    sorted_numbers: &[u64],
+    rules: &JoltageRules,
+    mut random: impl FnMut() -> u64,
+) -> Result<Option<Vec<u64>>, Overflow> {
+    let total = count_paths(sorted_numbers, rules)?;
+    if total == 0 {
+        return Ok(None);
+    }
+    // Rejects the top partial bucket so every k is equally likely
+    let limit = u64::MAX - u64::MAX % total;
+    let k = loop {
+        let r = random();
+        if r < limit {
+            break r % total;
+        }
+    };
+    kth_arrangement(sorted_numbers, rules, k)
+}
+
+// Fewest adapters from the first to the last, lexicographically first among equals
+fn shortest_arrangement(sorted_numbers: &[u64], rules: &JoltageRules) -> Option<Vec<u64>> {
+    let len = sorted_numbers.len();
+    // Steps to the last adapter and the adapter to go to next
+    let mut best: Vec<Option<(usize, usize)>> = vec![None; len];
+    for i in (0..len).rev() {
+        if i + 1 == len {
+            best[i] = Some((0, i));
+            continue;
+        }
+        best[i] = next_steps(sorted_numbers, rules, i)
+            .filter_map(|j| best[j].map(|(steps, _)| (steps + 1, j)))
+            .min_by_key(|&(steps, j)| (steps, j));
+    }
+    let mut i = 0;
+    let mut arrangement = vec![*sorted_numbers.first()?];
+    while i + 1 < len {
+        i = best[i]?.1;
+        arrangement.push(sorted_numbers[i]);
+    }
+    Some(arrangement)
+}
+
+// Xorshift seeded from the clock, good enough for picking an example
+fn clock_random() -> impl FnMut() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    let mut state = nanos | 1;
+    move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    }
+}
+
+fn main() {
+    let input = include_str!("input");
+    let (numbers, rest) = many1(|inp| endline_terminated(unsigned_number, inp), input)
+        .expect("Failed to parse input");
+    assert!(rest.is_empty());
+
+    // Pass gaps like "1,2,5" to change the joltage rules, and "big" to count with arbitrary precision
+    let mut rules = JoltageRules::default();
+    let mut big = false;
+    for arg in env::args().skip(1) {
+        if arg == "big" {
+            big = true;
+        } else {
+            rules = JoltageRules::parse(&arg).expect("Gaps must be positive numbers like 1,2,3");
+        }
+    }
+
+    let numbers = match adapter_chain(numbers, &rules) {
+        Ok(chain) => chain,
+        Err(e) => {
+            println!("Invalid adapters: {}", e);
+            return;
+        }
+    };
+
+    let histogram = gap_histogram(&numbers);
+    let ones = *histogram.get(&1).unwrap_or(&0);
+    let threes = *histogram.get(&3).unwrap_or(&0);
+    let multiple = ones
+        .checked_mul(threes)
+        .expect("Multiple of differences overflowed");
+    println!("Part 1: Multiple of ones and threes: {}", multiple);
+    println!("Differences: {:?}", histogram);
+
+    if big {
+        println!(
+            "Part 2: Number of alternatives: {}",
+            count_paths_big(&numbers, &rules)
+        );
+    } else {
+        match count_paths(&numbers, &rules) {
+            Ok(count) => println!("Part 2: Number of alternatives: {}", count),
+            Err(e) => println!("Part 2: {}, try again with \"big\"", e),
+        }
+    }
+
+    if let Some(shortest) = shortest_arrangement(&numbers, &rules) {
+        println!("Shortest arrangement uses {} adapters", shortest.len());
+    }
+    if let Some(first) = arrangements(&numbers, &rules).next() {
+        println!("First arrangement uses {} adapters", first.len());
+    }
+    match sample_arrangement(&numbers, &rules, clock_random()) {
+        Ok(Some(sample)) => println!("Random arrangement uses {} adapters", sample.len()),
+        Ok(None) => println!("No arrangements to sample"),
+        Err(e) => println!("Could not sample an arrangement: {}", e),
+    }
 }
 
 #[cfg(test)]
@@ -54,6 +460,186 @@ mod test {
             8, 17, 7, 9, 4, 2, 34, 10, 3, 0, 52,
         ];
         input.sort_unstable();
-        assert_eq!(count_paths(&input), 19208);
+        let rules = JoltageRules::default();
+        assert_eq!(count_paths(&input, &rules), Ok(19208));
+        assert_eq!(count_paths_big(&input, &rules), BigUint::from(19208));
+    }
+
+    #[test]
+    fn joltage_rules() {
+        assert_eq!(JoltageRules::parse("1,2,3"), Some(JoltageRules::default()));
+        let rules = JoltageRules::parse("5, 1,2").unwrap();
+        assert_eq!(rules.max_gap(), 5);
+        assert!(rules.allows(2) && !rules.allows(3));
+        assert_eq!(JoltageRules::parse(""), None);
+        assert_eq!(JoltageRules::parse("0,1"), None);
+        assert_eq!(JoltageRules::parse("1,x"), None);
+    }
+
+    #[test]
+    fn count_paths_other_gaps() {
+        let rules = JoltageRules::parse("1,2,5").unwrap();
+        // Only 0-1-2-7 and 0-2-7
+        assert_eq!(count_paths(&[0, 1, 2, 7], &rules), Ok(2));
+        assert_eq!(count_paths(&[0, 3, 4, 9], &rules), Ok(0));
+        assert_eq!(count_paths(&[0, 1, 3, 4, 6], &rules), Ok(2));
+        let evens = JoltageRules::parse("2").unwrap();
+        assert_eq!(count_paths(&[0, 1, 2, 4], &evens), Ok(1));
+        assert_eq!(count_paths(&[], &evens), Ok(0));
+        assert_eq!(count_paths(&[7], &evens), Ok(1));
+    }
+
+    fn example() -> Vec<u64> {
+        let mut input = vec![0, 16, 10, 15, 5, 1, 11, 7, 19, 6, 12, 4, 22];
+        input.sort_unstable();
+        input
+    }
+
+    #[test]
+    fn enumerate_arrangements() {
+        let input = example();
+        let rules = JoltageRules::default();
+        let all: Vec<Vec<u64>> = arrangements(&input, &rules).collect();
+        assert_eq!(all.len(), 8);
+        assert_eq!(all[0], vec![0, 1, 4, 5, 6, 7, 10, 11, 12, 15, 16, 19, 22]);
+        assert_eq!(all[7], vec![0, 1, 4, 7, 10, 12, 15, 16, 19, 22]);
+        let mut sorted = all.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(all, sorted);
+        for arrangement in all.iter() {
+            assert!(arrangement.windows(2).all(|w| rules.allows(w[1] - w[0])));
+            assert_eq!(arrangement.last(), Some(&22));
+        }
+        assert_eq!(arrangements(&[0, 1, 9], &rules).count(), 0);
+        assert_eq!(arrangements(&[], &rules).count(), 0);
+        assert_eq!(
+            arrangements(&[4], &rules).collect::<Vec<_>>(),
+            vec![vec![4]]
+        );
+    }
+
+    #[test]
+    fn kth_matches_enumeration() {
+        let mut input = vec![
+            28, 33, 18, 42, 31, 14, 46, 20, 48, 47, 24, 23, 49, 45, 19, 38, 39, 11, 1, 32, 25, 35,
+            8, 17, 7, 9, 4, 2, 34, 10, 3, 0, 52,
+        ];
+        input.sort_unstable();
+        for rules in [JoltageRules::default(), JoltageRules::parse("1,3").unwrap()].iter() {
+            let all: Vec<Vec<u64>> = arrangements(&input, rules).collect();
+            assert_eq!(all.len() as u64, count_paths(&input, rules).unwrap());
+            for (k, arrangement) in all.iter().enumerate() {
+                assert_eq!(
+                    kth_arrangement(&input, rules, k as u64),
+                    Ok(Some(arrangement.clone()))
+                );
+            }
+            assert_eq!(kth_arrangement(&input, rules, all.len() as u64), Ok(None));
+        }
+    }
+
+    #[test]
+    fn sample_uses_random_source() {
+        let input = example();
+        let rules = JoltageRules::default();
+        let all: Vec<Vec<u64>> = arrangements(&input, &rules).collect();
+        for k in 0..8 {
+            let sample = sample_arrangement(&input, &rules, || k + 8);
+            assert_eq!(sample, Ok(Some(all[k as usize].clone())));
+        }
+        // The top of the range is rejected so the remainders stay uniform
+        let mut draws = vec![u64::MAX, 3].into_iter();
+        let sample = sample_arrangement(&input, &rules, || draws.next().unwrap());
+        assert_eq!(sample, Ok(Some(all[3].clone())));
+        let mut random = clock_random();
+        let sample = sample_arrangement(&input, &rules, &mut random)
+            .unwrap()
+            .unwrap();
+        assert!(all.contains(&sample));
+        assert_eq!(sample_arrangement(&[0, 9], &rules, random), Ok(None));
+    }
+
+    #[test]
+    fn shortest() {
+        let input = example();
+        let rules = JoltageRules::default();
+        let shortest = shortest_arrangement(&input, &rules).unwrap();
+        let min_len = arrangements(&input, &rules).map(|a| a.len()).min();
+        assert_eq!(Some(shortest.len()), min_len);
+        assert_eq!(shortest, vec![0, 1, 4, 7, 10, 12, 15, 16, 19, 22]);
+        assert_eq!(
+            shortest_arrangement(&[0, 1, 2, 3, 4], &rules),
+            Some(vec![0, 1, 4])
+        );
+        assert_eq!(shortest_arrangement(&[0, 5], &rules), None);
+        assert_eq!(shortest_arrangement(&[], &rules), None);
+    }
+
+    #[test]
+    fn chain_validation() {
+        let rules = JoltageRules::default();
+        assert_eq!(
+            adapter_chain(vec![3, 1, 2], &rules),
+            Ok(vec![0, 1, 2, 3, 6])
+        );
+        assert_eq!(adapter_chain(vec![], &rules), Ok(vec![0, 3]));
+        assert_eq!(
+            adapter_chain(vec![1, 4, 4, 5], &rules),
+            Err(ChainError::Duplicate { rating: 4 })
+        );
+        assert_eq!(
+            adapter_chain(vec![0, 1], &rules),
+            Err(ChainError::Duplicate { rating: 0 })
+        );
+        assert_eq!(
+            adapter_chain(vec![1, 2, 6, 7], &rules),
+            Err(ChainError::Gap { from: 2, to: 6 })
+        );
+        assert_eq!(
+            adapter_chain(vec![u64::MAX], &rules),
+            Err(ChainError::RatingTooLarge { rating: u64::MAX })
+        );
+        let odd = JoltageRules::parse("1,3").unwrap();
+        assert_eq!(
+            adapter_chain(vec![2, 4, 6], &odd),
+            Err(ChainError::Unreachable { stuck_at: 0 })
+        );
+        let skips = JoltageRules::parse("2,3").unwrap();
+        assert_eq!(
+            adapter_chain(vec![1, 3, 4], &skips),
+            Err(ChainError::Unreachable { stuck_at: 3 })
+        );
+        assert_eq!(
+            ChainError::Gap { from: 2, to: 6 }.to_string(),
+            "chain breaks between 2 and 6"
+        );
+    }
+
+    #[test]
+    fn histogram_of_gaps() {
+        let histogram = gap_histogram(&[0, 1, 4, 5, 10]);
+        let expected: BTreeMap<u64, usize> = [(1, 2), (3, 1), (5, 1)].iter().copied().collect();
+        assert_eq!(histogram, expected);
+    }
+
+    #[test]
+    fn count_paths_overflow() {
+        // Steps of one grow like the tribonacci numbers, passing u64 within a hundred adapters
+        let chain: Vec<u64> = (0..100).collect();
+        let index = match count_paths(&chain, &JoltageRules::default()) {
+            Err(Overflow { index }) => index,
+            res => panic!("Expected overflow, got {:?}", res),
+        };
+        assert!(index < 30);
+        let short: Vec<u64> = (0..40).collect();
+        assert_eq!(
+            count_paths_big(&short, &JoltageRules::default()).to_u64(),
+            count_paths(&short, &JoltageRules::default()).ok()
+        );
+        assert_eq!(
+            count_paths_big(&chain, &JoltageRules::default()).to_string(),
+            "98079530178586034536500564"
+        );
     }
 }
@@ -1,6 +1,12 @@
-use parser::{any, either, endline, eof, fixed, make_parser};
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+use parser::{grid, GridError};
+
+const MAX_GENERATIONS: usize = 10_000;
+const CUBE_CYCLES: usize = 6;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 enum Tile {
     Floor,
     Empty,
@@ -13,37 +19,72 @@ struct Seats {
     width: usize,
 }
 
-impl Seats {
-    fn parse(mut input: &str) -> Option<Seats> {
-        let mut tiles = Vec::new();
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Stabilized { generation: usize },
+    Cycle { start: usize, period: usize },
+    BudgetExceeded,
+}
 
-        let width = input.find('\n')?;
-        let parse_tile = any!(
-            make_parser!(fixed, "."),
-            make_parser!(fixed, "L"),
-            make_parser!(fixed, "#")
-        );
+#[derive(Debug, PartialEq)]
+struct Run {
+    outcome: Outcome,
+    // Active count for every distinct generation, starting with the initial layout
+    active_history: Vec<usize>,
+}
 
-        let parse_tile = |inp| {
-            parse_tile(inp).and_then(|(t, r)| match t {
-                "." => Some((Tile::Floor, r)),
-                "L" => Some((Tile::Empty, r)),
-                "#" => Some((Tile::Occupied, r)),
-                _ => None,
-            })
-        };
+#[derive(Clone, Copy)]
+struct Rule {
+    born: fn(usize) -> bool,
+    survives: fn(usize) -> bool,
+}
 
-        while !input.is_empty() {
-            for _ in 0..width {
-                let (tile, rest) = parse_tile(input)?;
-                tiles.push(tile);
-                input = rest;
-            }
-            let (_, rest) = either(endline, eof, input)?;
-            input = rest;
+impl Rule {
+    fn apply(&self, active: bool, neighbours: usize) -> bool {
+        if active {
+            (self.survives)(neighbours)
+        } else {
+            (self.born)(neighbours)
         }
-        assert!(input.is_empty());
-        Some(Seats { tiles, width })
+    }
+}
+
+const ADJACENT_RULE: Rule = Rule {
+    born: |n| n == 0,
+    survives: |n| n < 4,
+};
+
+const VISIBLE_RULE: Rule = Rule {
+    born: |n| n == 0,
+    survives: |n| n < 5,
+};
+
+const CONWAY_RULE: Rule = Rule {
+    born: |n| n == 3,
+    survives: |n| n == 2 || n == 3,
+};
+
+const HEX_RULE: Rule = Rule {
+    born: |n| n == 2,
+    survives: |n| n == 1 || n == 2,
+};
+
+trait Automaton {
+    type Snapshot: Hash + Eq;
+
+    fn snapshot(&self) -> Self::Snapshot;
+    fn active_count(&self) -> usize;
+}
+
+impl Seats {
+    fn parse(input: &str) -> Result<Seats, GridError> {
+        let (tiles, width) = grid(input, |c| match c {
+            '.' => Some(Tile::Floor),
+            'L' => Some(Tile::Empty),
+            '#' => Some(Tile::Occupied),
+            _ => None,
+        })?;
+        Ok(Seats { tiles, width })
     }
 
     fn get_adjacents(&self, x: usize, y: usize) -> Vec<Tile> {
@@ -61,30 +102,14 @@ impl Seats {
         tiles
     }
 
-    fn simulate(&mut self) -> bool {
-        let mut new_tiles = Vec::new();
-        for (i, tile) in self.tiles.iter().enumerate() {
-            let x = i % self.width;
-            let y = i / self.width;
-            let occupied_adjacents = self
-                .get_adjacents(x, y)
-                .iter()
-                .filter(|&&t| t == Tile::Occupied)
-                .count();
-            let new_tile = match (tile, occupied_adjacents) {
-                (Tile::Empty, 0) => Tile::Occupied,
-                (Tile::Occupied, n) if n >= 4 => Tile::Empty,
-                _ => *tile,
-            };
-            new_tiles.push(new_tile);
-        }
-        let ret = new_tiles == self.tiles;
-        self.tiles = new_tiles;
-        ret
+    fn count_adjacent(&self, x: usize, y: usize) -> usize {
+        self.get_adjacents(x, y)
+            .iter()
+            .filter(|&&t| t == Tile::Occupied)
+            .count()
     }
 
-    fn simulate_2(&mut self) -> bool {
-        let mut new_tiles = Vec::new();
+    fn count_visible(&self, x: usize, y: usize) -> usize {
         let dirs = [
             (-1, -1),
             (-1, 0),
@@ -96,36 +121,45 @@ impl Seats {
             (-1, 1),
         ];
 
-        for (i, tile) in self.tiles.iter().enumerate() {
-            let mut visible_occupied = 0;
-            let current_x = i % self.width;
-            let current_y = i / self.width;
-            for (dx, dy) in dirs.iter() {
-                let mut x = current_x as isize + dx;
-                let mut y = current_y as isize + dy;
-                loop {
-                    if x < 0 || y < 0 {
+        let mut visible_occupied = 0;
+        for (dx, dy) in dirs.iter() {
+            let mut xi = x as isize + dx;
+            let mut yi = y as isize + dy;
+            loop {
+                if xi < 0 || yi < 0 {
+                    break;
+                }
+                match self.get_tile(xi as usize, yi as usize) {
+                    Some(Tile::Occupied) => {
+                        visible_occupied += 1;
                         break;
                     }
-                    match self.get_tile(x as usize, y as usize) {
-                        Some(Tile::Occupied) => {
-                            visible_occupied += 1;
-                            break;
-                        }
-                        Some(Tile::Empty) | None => {
-                            break;
-                        }
-                        Some(Tile::Floor) => {
-                            x += dx;
-                            y += dy;
-                        }
-                    };
-                }
+                    Some(Tile::Empty) | None => {
+                        break;
+                    }
+                    Some(Tile::Floor) => {
+                        xi += dx;
+                        yi += dy;
+                    }
+                };
             }
-            let new_tile = match (tile, visible_occupied) {
-                (Tile::Empty, 0) => Tile::Occupied,
-                (Tile::Occupied, n) if n >= 5 => Tile::Empty,
-                _ => *tile,
+        }
+        visible_occupied
+    }
+
+    fn simulate_with(&mut self, count: fn(&Seats, usize, usize) -> usize, rule: Rule) -> bool {
+        let mut new_tiles = Vec::with_capacity(self.tiles.len());
+        for (i, tile) in self.tiles.iter().enumerate() {
+            let new_tile = match tile {
+                Tile::Floor => Tile::Floor,
+                _ => {
+                    let occupied = count(self, i % self.width, i / self.width);
+                    if rule.apply(*tile == Tile::Occupied, occupied) {
+                        Tile::Occupied
+                    } else {
+                        Tile::Empty
+                    }
+                }
             };
             new_tiles.push(new_tile);
         }
@@ -134,32 +168,232 @@ impl Seats {
         ret
     }
 
+    fn simulate(&mut self) -> bool {
+        self.simulate_with(Seats::count_adjacent, ADJACENT_RULE)
+    }
+
+    fn simulate_2(&mut self) -> bool {
+        self.simulate_with(Seats::count_visible, VISIBLE_RULE)
+    }
+
     fn get_tile(&self, x: usize, y: usize) -> Option<Tile> {
         if x >= self.width {
             return None;
         }
         self.tiles.get(x + y * self.width).copied()
     }
+
+    fn occupied_positions(&self) -> impl Iterator<Item = (i64, i64)> + '_ {
+        self.tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, &t)| t == Tile::Occupied)
+            .map(move |(i, _)| ((i % self.width) as i64, (i / self.width) as i64))
+    }
+
+    fn lift<const N: usize>(&self) -> SparseGrid<[i64; N]> {
+        assert!(N >= 2);
+        SparseGrid::new(self.occupied_positions().map(|(x, y)| {
+            let mut cell = [0; N];
+            cell[0] = x;
+            cell[1] = y;
+            cell
+        }))
+    }
+
+    fn lift_hex(&self) -> SparseGrid<Hex> {
+        SparseGrid::new(self.occupied_positions().map(|(q, r)| Hex { q, r }))
+    }
+}
+
+impl Automaton for Seats {
+    type Snapshot = Vec<Tile>;
+
+    fn snapshot(&self) -> Vec<Tile> {
+        self.tiles.clone()
+    }
+
+    fn active_count(&self) -> usize {
+        self.tiles.iter().filter(|&&t| t == Tile::Occupied).count()
+    }
+}
+
+trait Cell: Copy + Eq + Hash + Ord {
+    fn neighbours(&self) -> Vec<Self>;
+}
+
+// Axial coordinates
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
+struct Hex {
+    q: i64,
+    r: i64,
+}
+
+impl Cell for Hex {
+    fn neighbours(&self) -> Vec<Hex> {
+        [(1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)]
+            .iter()
+            .map(|(dq, dr)| Hex {
+                q: self.q + dq,
+                r: self.r + dr,
+            })
+            .collect()
+    }
+}
+
+impl<const N: usize> Cell for [i64; N] {
+    fn neighbours(&self) -> Vec<Self> {
+        let mut neighbours = Vec::new();
+        let mut offset = [-1; N];
+        loop {
+            if offset.iter().any(|&d| d != 0) {
+                let mut neighbour = *self;
+                for (c, d) in neighbour.iter_mut().zip(offset.iter()) {
+                    *c += d;
+                }
+                neighbours.push(neighbour);
+            }
+            // Step the offset like an odometer over -1..=1 in every dimension
+            match offset.iter().position(|&d| d < 1) {
+                Some(i) => {
+                    offset[i] += 1;
+                    for d in offset[..i].iter_mut() {
+                        *d = -1;
+                    }
+                }
+                None => return neighbours,
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct SparseGrid<C: Cell> {
+    active: HashSet<C>,
+}
+
+impl<C: Cell> SparseGrid<C> {
+    fn new(active: impl IntoIterator<Item = C>) -> Self {
+        SparseGrid {
+            active: active.into_iter().collect(),
+        }
+    }
+
+    // Only cells next to an active cell are considered, so rules that bring
+    // cells to life without any active neighbours don't work on a sparse grid
+    fn simulate_with(&mut self, rule: Rule) -> bool {
+        let mut counts = HashMap::new();
+        for cell in self.active.iter() {
+            for neighbour in cell.neighbours() {
+                *counts.entry(neighbour).or_insert(0) += 1;
+            }
+        }
+        let mut new_active: HashSet<C> = counts
+            .iter()
+            .filter(|(cell, &n)| rule.apply(self.active.contains(cell), n))
+            .map(|(cell, _)| *cell)
+            .collect();
+        if rule.apply(true, 0) {
+            new_active.extend(self.active.iter().filter(|c| !counts.contains_key(c)));
+        }
+        let ret = new_active == self.active;
+        self.active = new_active;
+        ret
+    }
+}
+
+impl<C: Cell> Automaton for SparseGrid<C> {
+    type Snapshot = Vec<C>;
+
+    fn snapshot(&self) -> Vec<C> {
+        let mut cells: Vec<C> = self.active.iter().copied().collect();
+        cells.sort();
+        cells
+    }
+
+    fn active_count(&self) -> usize {
+        self.active.len()
+    }
+}
+
+fn run<A: Automaton>(
+    automaton: &mut A,
+    step: impl Fn(&mut A) -> bool,
+    max_generations: usize,
+) -> Run {
+    let mut seen = HashMap::new();
+    seen.insert(automaton.snapshot(), 0);
+    let mut active_history = vec![automaton.active_count()];
+
+    for generation in 1..=max_generations {
+        if step(automaton) {
+            return Run {
+                outcome: Outcome::Stabilized {
+                    generation: generation - 1,
+                },
+                active_history,
+            };
+        }
+        if let Some(start) = seen.insert(automaton.snapshot(), generation) {
+            return Run {
+                outcome: Outcome::Cycle {
+                    start,
+                    period: generation - start,
+                },
+                active_history,
+            };
+        }
+        active_history.push(automaton.active_count());
+    }
+    Run {
+        outcome: Outcome::BudgetExceeded,
+        active_history,
+    }
+}
+
+fn describe(run: &Run) -> String {
+    match run.outcome {
+        Outcome::Stabilized { generation } => format!("stabilized at gen {}", generation),
+        Outcome::Cycle { start, period } => format!("period {} starting at gen {}", period, start),
+        Outcome::BudgetExceeded => "budget exceeded".to_owned(),
+    }
 }
 
 fn main() {
     let input = include_str!("input");
-    let mut seats = Seats::parse(input).unwrap();
+    let mut seats = Seats::parse(input).expect("Failed to parse seats");
 
-    while !seats.simulate() {}
-    let final_occupied_count_1 = seats.tiles.iter().filter(|&&t| t == Tile::Occupied).count();
+    let run_1 = run(&mut seats, Seats::simulate, MAX_GENERATIONS);
+    println!(
+        "Part 1: {}, final number of occupied seats: {}",
+        describe(&run_1),
+        seats.active_count()
+    );
+
+    // Exactly CUBE_CYCLES generations, even if the layout repeats earlier
+    let mut cubes = seats.lift::<3>();
+    let mut hypercubes = seats.lift::<4>();
+    let mut hexes = seats.lift_hex();
+    for _ in 0..CUBE_CYCLES {
+        cubes.simulate_with(CONWAY_RULE);
+        hypercubes.simulate_with(CONWAY_RULE);
+        hexes.simulate_with(HEX_RULE);
+    }
     println!(
-        "Part 1: final number of occupied seats: {}",
-        final_occupied_count_1
+        "Part 1 layout after {} cycles: {} cubes (3D), {} cubes (4D), {} hex tiles",
+        CUBE_CYCLES,
+        cubes.active_count(),
+        hypercubes.active_count(),
+        hexes.active_count()
     );
 
-    let mut seats = Seats::parse(input).unwrap();
+    let mut seats = Seats::parse(input).expect("Failed to parse seats");
 
-    while !seats.simulate_2() {}
-    let final_occupied_count_2 = seats.tiles.iter().filter(|&&t| t == Tile::Occupied).count();
+    let run_2 = run(&mut seats, Seats::simulate_2, MAX_GENERATIONS);
     println!(
-        "Part 2: final number of occupied seats: {}",
-        final_occupied_count_2
+        "Part 2: {}, final number of occupied seats: {}",
+        describe(&run_2),
+        seats.active_count()
     );
 }
 
@@ -174,13 +408,37 @@ mod test {
         let seats = Seats::parse(input);
         assert_eq!(
             seats,
-            Some(Seats {
+            Ok(Seats {
                 tiles: vec![Occupied, Floor, Empty, Empty, Occupied, Floor, Floor, Occupied, Empty],
                 width: 3
             })
         );
     }
 
+    #[test]
+    fn parse_seats_errors() {
+        assert_eq!(
+            Seats::parse("#.L\r\nL#.\r\n.#L\r\n").map(|s| s.tiles.len()),
+            Ok(9)
+        );
+        assert_eq!(
+            Seats::parse("#.L\nL#\n.#L"),
+            Err(GridError::RaggedRow {
+                row: 2,
+                expected: 3,
+                found: 2
+            })
+        );
+        assert_eq!(
+            Seats::parse("#.L\nL#.\n.xL"),
+            Err(GridError::UnexpectedChar {
+                row: 3,
+                column: 2,
+                found: 'x'
+            })
+        );
+    }
+
     #[test]
     fn test_get_adjacents() {
         use Tile::*;
@@ -202,4 +460,95 @@ mod test {
             vec![Floor, Floor, Floor, Empty, Empty, Occupied, Occupied, Occupied]
         );
     }
+
+    fn flip_all(seats: &mut Seats) -> bool {
+        for tile in seats.tiles.iter_mut() {
+            *tile = match tile {
+                Tile::Empty => Tile::Occupied,
+                Tile::Occupied => Tile::Empty,
+                Tile::Floor => Tile::Floor,
+            };
+        }
+        false
+    }
+
+    #[test]
+    fn run_stabilizes() {
+        let input = "L.LL.LL.LL\nLLLLLLL.LL\nL.L.L..L..\nLLLL.LL.LL\nL.LL.LL.LL\nL.LLLLL.LL\n..L.L.....\nLLLLLLLLLL\nL.LLLLLL.L\nL.LLLLL.LL";
+        let mut seats = Seats::parse(input).unwrap();
+        let run = run(&mut seats, Seats::simulate, 100);
+        assert_eq!(run.outcome, Outcome::Stabilized { generation: 5 });
+        assert_eq!(run.active_history, vec![0, 71, 20, 51, 30, 37]);
+        assert_eq!(seats.active_count(), 37);
+    }
+
+    #[test]
+    fn run_detects_cycle() {
+        let mut seats = Seats::parse("L#.\n.L#").unwrap();
+        let run = run(&mut seats, flip_all, 100);
+        assert_eq!(
+            run.outcome,
+            Outcome::Cycle {
+                start: 0,
+                period: 2
+            }
+        );
+        assert_eq!(run.active_history, vec![2, 2]);
+    }
+
+    #[test]
+    fn run_budget_exceeded() {
+        let mut seats = Seats::parse("L#.\n.L#").unwrap();
+        let run = run(&mut seats, flip_all, 1);
+        assert_eq!(run.outcome, Outcome::BudgetExceeded);
+        assert_eq!(run.active_history, vec![2, 2]);
+    }
+
+    #[test]
+    fn cube_neighbours() {
+        assert_eq!([0i64; 2].neighbours().len(), 8);
+        assert_eq!([0i64; 3].neighbours().len(), 26);
+        let neighbours = [0i64; 4].neighbours();
+        assert_eq!(neighbours.len(), 80);
+        assert!(!neighbours.contains(&[0; 4]));
+        assert!(neighbours.contains(&[1, -1, 0, 1]));
+    }
+
+    #[test]
+    fn conway_cubes() {
+        let seats = Seats::parse(".#.\n..#\n###").unwrap();
+
+        let mut cubes = seats.lift::<3>();
+        assert_eq!(cubes.active_count(), 5);
+        for _ in 0..6 {
+            cubes.simulate_with(CONWAY_RULE);
+        }
+        assert_eq!(cubes.active_count(), 112);
+
+        let mut hypercubes = seats.lift::<4>();
+        for _ in 0..6 {
+            hypercubes.simulate_with(CONWAY_RULE);
+        }
+        assert_eq!(hypercubes.active_count(), 848);
+    }
+
+    #[test]
+    fn hex_flipping() {
+        let mut single = SparseGrid::new(vec![Hex { q: 0, r: 0 }]);
+        let run = run(&mut single, |g| g.simulate_with(HEX_RULE), 10);
+        assert_eq!(run.outcome, Outcome::Stabilized { generation: 1 });
+        assert_eq!(run.active_history, vec![1, 0]);
+
+        let mut pair = SparseGrid::new(vec![Hex { q: 0, r: 0 }, Hex { q: 1, r: 0 }]);
+        pair.simulate_with(HEX_RULE);
+        assert_eq!(
+            pair.snapshot(),
+            vec![
+                Hex { q: 0, r: 0 },
+                Hex { q: 0, r: 1 },
+                Hex { q: 1, r: -1 },
+                Hex { q: 1, r: 0 },
+            ]
+        );
+    }
 }
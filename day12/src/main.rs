@@ -1,3 +1,9 @@
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::ops::{Add, Mul, Neg, Sub};
+
 use parser::{any, endline_terminated, fixed, make_parser, many1, unsigned_number};
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -50,25 +56,128 @@ impl Move {
     }
 }
 
-struct Ship {
-    position: (i64, i64),
-    step: (i64, i64),
-    waypoint: (i64, i64),
+// A turn the chosen numeric mode can't represent
+#[derive(Debug, PartialEq)]
+struct RotationError {
+    degrees: u64,
+}
+
+impl fmt::Display for RotationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot turn {} degrees exactly", self.degrees)
+    }
+}
+
+impl Error for RotationError {}
+
+trait Coordinate:
+    Copy
+    + PartialOrd
+    + fmt::Debug
+    + fmt::Display
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Neg<Output = Self>
+{
+    fn from_i64(n: i64) -> Self;
+    fn to_f64(self) -> f64;
+    fn abs(self) -> Self;
+    // Counterclockwise by the given degrees, if the result can be represented
+    fn rotate(vector: (Self, Self), degrees: i64) -> Option<(Self, Self)>;
+}
+
+// Multiples of 90 degrees only swap and negate, so they stay exact in every mode
+fn quarter_turns<T: Coordinate>((x, y): (T, T), degrees: i64) -> Option<(T, T)> {
+    if degrees % 90 != 0 {
+        return None;
+    }
+    Some(match degrees.rem_euclid(360) / 90 {
+        0 => (x, y),
+        1 => (-y, x),
+        2 => (-x, -y),
+        _ => (y, -x),
+    })
+}
+
+impl Coordinate for i64 {
+    fn from_i64(n: i64) -> Self {
+        n
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn abs(self) -> Self {
+        i64::abs(self)
+    }
+
+    fn rotate(vector: (Self, Self), degrees: i64) -> Option<(Self, Self)> {
+        quarter_turns(vector, degrees)
+    }
+}
+
+impl Coordinate for f64 {
+    fn from_i64(n: i64) -> Self {
+        n as f64
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn abs(self) -> Self {
+        f64::abs(self)
+    }
+
+    fn rotate(vector: (Self, Self), degrees: i64) -> Option<(Self, Self)> {
+        quarter_turns(vector, degrees).or_else(|| {
+            let (x, y) = vector;
+            let (sin, cos) = (degrees as f64).to_radians().sin_cos();
+            Some((x * cos - y * sin, x * sin + y * cos))
+        })
+    }
 }
 
-impl Ship {
-    fn new() -> Ship {
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Numeric {
+    Exact,
+    Float,
+}
+
+struct Ship<T> {
+    position: (T, T),
+    step: (T, T),
+    waypoint: (T, T),
+}
+
+impl<T: Coordinate> Ship<T> {
+    fn new() -> Ship<T> {
+        let int = T::from_i64;
         Ship {
-            position: (0, 0),
-            step: (1, 0),
-            waypoint: (10, 1),
+            position: (int(0), int(0)),
+            step: (int(1), int(0)),
+            waypoint: (int(10), int(1)),
         }
     }
 
-    fn move_1(&mut self, movement: Move) {
+    fn turn(vector: (T, T), movement: Move) -> Result<(T, T), RotationError> {
+        let degrees = movement.amount as i64;
+        let degrees = if movement.direction == Direction::Right {
+            -degrees
+        } else {
+            degrees
+        };
+        T::rotate(vector, degrees).ok_or(RotationError {
+            degrees: movement.amount,
+        })
+    }
+
+    fn move_1(&mut self, movement: Move) -> Result<(), RotationError> {
         use Direction::*;
         let (x, y) = self.position;
-        let amount = movement.amount as i64;
+        let amount = T::from_i64(movement.amount as i64);
         self.position = match movement.direction {
             East => (x + amount, y),
             West => (x - amount, y),
@@ -79,48 +188,208 @@ impl Ship {
                 (x + dx * amount, y + dy * amount)
             }
             Right | Left => {
-                assert_eq!(movement.amount % 90, 0);
-                let quarter_turns = movement.amount / 90;
-                let steps = if movement.direction == Right {
-                    [(1, 0), (0, -1), (-1, 0), (0, 1)]
-                } else {
-                    [(1, 0), (0, 1), (-1, 0), (0, -1)]
-                };
-                let current_step_pos = steps
-                    .iter()
-                    .position(|&p| p == self.step)
-                    .expect("Illegal value assigned to step");
-                let new_step_pos = (current_step_pos + quarter_turns as usize) % steps.len();
-                self.step = steps[new_step_pos];
+                self.step = Self::turn(self.step, movement)?;
                 self.position
             }
         };
+        Ok(())
     }
 
-    fn move_2(&mut self, movement: Move) {
+    fn move_2(&mut self, movement: Move) -> Result<(), RotationError> {
         use Direction::*;
         let (own_x, own_y) = self.position;
         let (wp_x, wp_y) = self.waypoint;
-        let amount = movement.amount as i64;
+        let amount = T::from_i64(movement.amount as i64);
         match movement.direction {
             East => self.waypoint = (wp_x + amount, wp_y),
             West => self.waypoint = (wp_x - amount, wp_y),
             South => self.waypoint = (wp_x, wp_y - amount),
             North => self.waypoint = (wp_x, wp_y + amount),
             Forward => self.position = (own_x + wp_x * amount, own_y + wp_y * amount),
-            Right | Left => {
-                assert_eq!(movement.amount % 90, 0);
-                let quarter_turns = movement.amount / 90;
-                for _ in 0..quarter_turns {
-                    let (wp_x, wp_y) = self.waypoint;
-                    if movement.direction == Right {
-                        self.waypoint = (wp_y, -wp_x);
-                    } else {
-                        self.waypoint = (-wp_y, wp_x);
-                    }
+            Right | Left => self.waypoint = Self::turn(self.waypoint, movement)?,
+        };
+        Ok(())
+    }
+}
+
+fn manhattan<T: Coordinate>((x, y): (T, T)) -> T {
+    x.abs() + y.abs()
+}
+
+fn partial_min<T: Coordinate>(a: T, b: T) -> T {
+    if b < a {
+        b
+    } else {
+        a
+    }
+}
+
+fn partial_max<T: Coordinate>(a: T, b: T) -> T {
+    if b > a {
+        b
+    } else {
+        a
+    }
+}
+
+// Where the ship and its waypoint were at the start and after every move
+struct Trajectory<T> {
+    positions: Vec<(T, T)>,
+    waypoints: Vec<(T, T)>,
+}
+
+impl<T: Coordinate> Trajectory<T> {
+    fn final_distance(&self) -> T {
+        manhattan(*self.positions.last().unwrap())
+    }
+
+    // Lowest and highest corners of the ship's path
+    fn bounding_box(&self) -> ((T, T), (T, T)) {
+        let first = self.positions[0];
+        self.positions.iter().fold(
+            (first, first),
+            |((min_x, min_y), (max_x, max_y)), &(x, y)| {
+                (
+                    (partial_min(min_x, x), partial_min(min_y, y)),
+                    (partial_max(max_x, x), partial_max(max_y, y)),
+                )
+            },
+        )
+    }
+
+    // Straight line length of every leg added up
+    fn distance_travelled(&self) -> f64 {
+        self.positions
+            .windows(2)
+            .map(|leg| {
+                let dx = (leg[1].0 - leg[0].0).to_f64();
+                let dy = (leg[1].1 - leg[0].1).to_f64();
+                dx.hypot(dy)
+            })
+            .sum()
+    }
+
+    // By Manhattan distance from the start, the earliest one on ties
+    fn furthest_point(&self) -> (T, T) {
+        self.positions
+            .iter()
+            .copied()
+            .fold(self.positions[0], |furthest, p| {
+                if manhattan(p) > manhattan(furthest) {
+                    p
+                } else {
+                    furthest
                 }
+            })
+    }
+
+    // The path as a polyline, north up, optionally with a line from each stop to its waypoint
+    fn svg(&self, waypoints: bool) -> String {
+        let flip = |(x, y): (T, T)| (x.to_f64(), 0.0 - y.to_f64());
+        let mut points: Vec<(f64, f64)> = self.positions.iter().map(|&p| flip(p)).collect();
+        if waypoints {
+            points.extend(
+                self.positions
+                    .iter()
+                    .zip(self.waypoints.iter())
+                    .map(|(&(x, y), &(wx, wy))| flip((x + wx, y + wy))),
+            );
+        }
+        let min_x = points.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+        let min_y = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+        let max_x = points.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+        let max_y = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+        let margin = ((max_x - min_x).max(max_y - min_y) / 50.0).max(1.0);
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">\n",
+            min_x - margin,
+            min_y - margin,
+            max_x - min_x + 2.0 * margin,
+            max_y - min_y + 2.0 * margin
+        );
+        if waypoints {
+            for (&(x, y), &(wx, wy)) in self.positions.iter().zip(self.waypoints.iter()) {
+                let (x1, y1) = flip((x, y));
+                let (x2, y2) = flip((x + wx, y + wy));
+                svg += &format!(
+                    "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"orange\" vector-effect=\"non-scaling-stroke\"/>\n",
+                    x1, y1, x2, y2
+                );
             }
-        };
+        }
+        let path: Vec<String> = self
+            .positions
+            .iter()
+            .map(|&p| {
+                let (x, y) = flip(p);
+                format!("{},{}", x, y)
+            })
+            .collect();
+        svg += &format!(
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"navy\" vector-effect=\"non-scaling-stroke\"/>\n",
+            path.join(" ")
+        );
+        svg += "</svg>\n";
+        svg
+    }
+}
+
+fn record<T: Coordinate>(
+    moves: &[Move],
+    step: fn(&mut Ship<T>, Move) -> Result<(), RotationError>,
+) -> Result<Trajectory<T>, RotationError> {
+    let mut ship = Ship::new();
+    let mut trajectory = Trajectory {
+        positions: vec![ship.position],
+        waypoints: vec![ship.waypoint],
+    };
+    for m in moves.iter() {
+        step(&mut ship, *m)?;
+        trajectory.positions.push(ship.position);
+        trajectory.waypoints.push(ship.waypoint);
+    }
+    Ok(trajectory)
+}
+
+fn report<T: Coordinate>(part: u32, trajectory: &Result<Trajectory<T>, RotationError>) {
+    match trajectory {
+        Ok(trajectory) => {
+            println!(
+                "Part {}: Manhattan distance: {}",
+                part,
+                trajectory.final_distance()
+            );
+            let ((min_x, min_y), (max_x, max_y)) = trajectory.bounding_box();
+            println!(
+                "Part {}: Stayed within ({}, {}) to ({}, {}), travelled {:.1}, furthest at {:?}",
+                part,
+                min_x,
+                min_y,
+                max_x,
+                max_y,
+                trajectory.distance_travelled(),
+                trajectory.furthest_point()
+            );
+        }
+        Err(e) => println!("Part {}: {}, try again with \"float\"", part, e),
+    }
+}
+
+fn navigate<T: Coordinate>(moves: &[Move], svg_target: Option<&str>) {
+    let first = record::<T>(moves, Ship::move_1);
+    report(1, &first);
+    let second = record::<T>(moves, Ship::move_2);
+    report(2, &second);
+
+    // Draws the part 2 route, to the terminal for "-" or to a file otherwise
+    if let (Some(target), Ok(trajectory)) = (svg_target, &second) {
+        let svg = trajectory.svg(true);
+        if target == "-" {
+            print!("{}", svg);
+        } else {
+            fs::write(target, svg).expect("Failed to write SVG");
+        }
     }
 }
 
@@ -130,21 +399,21 @@ fn main() {
         many1(make_parser!(endline_terminated, Move::parse), input).expect("Failed to parse input");
     assert!(rest.is_empty());
 
-    let mut ship = Ship::new();
-    for m in moves.iter() {
-        ship.move_1(*m);
+    // Pass "float" to allow turns that aren't multiples of 90 degrees, and anything else as
+    // the place to write an SVG of the route
+    let mut numeric = Numeric::Exact;
+    let mut svg_target = None;
+    for arg in env::args().skip(1) {
+        if arg == "float" {
+            numeric = Numeric::Float;
+        } else {
+            svg_target = Some(arg);
+        }
     }
-    let (x, y) = ship.position;
-    let manhattan_distance = x.abs() + y.abs();
-    println!("Part 1: Manhattan distance: {}", manhattan_distance);
-
-    let mut ship = Ship::new();
-    for m in moves.iter() {
-        ship.move_2(*m);
+    match numeric {
+        Numeric::Exact => navigate::<i64>(&moves, svg_target.as_deref()),
+        Numeric::Float => navigate::<f64>(&moves, svg_target.as_deref()),
     }
-    let (x, y) = ship.position;
-    let manhattan_distance = x.abs() + y.abs();
-    println!("Part 2: Manhattan distance: {}", manhattan_distance);
 }
 
 #[cfg(test)]
@@ -172,41 +441,152 @@ mod test {
     #[test]
     fn move_ship_1() {
         use Direction::*;
-        let mut ship = Ship::new();
-        ship.move_1(Move::new(Forward, 10));
+        let mut ship = Ship::<i64>::new();
+        ship.move_1(Move::new(Forward, 10)).unwrap();
         assert_eq!(ship.position, (10, 0));
-        ship.move_1(Move::new(North, 3));
+        ship.move_1(Move::new(North, 3)).unwrap();
         assert_eq!(ship.position, (10, 3));
-        ship.move_1(Move::new(Forward, 7));
+        ship.move_1(Move::new(Forward, 7)).unwrap();
         assert_eq!(ship.position, (17, 3));
-        ship.move_1(Move::new(Right, 90));
+        ship.move_1(Move::new(Right, 90)).unwrap();
         assert_eq!(ship.position, (17, 3));
-        ship.move_1(Move::new(Forward, 11));
+        ship.move_1(Move::new(Forward, 11)).unwrap();
         assert_eq!(ship.position, (17, -8));
-        ship.move_1(Move::new(Left, 270));
+        ship.move_1(Move::new(Left, 270)).unwrap();
         assert_eq!(ship.position, (17, -8));
-        ship.move_1(Move::new(Forward, 7));
+        ship.move_1(Move::new(Forward, 7)).unwrap();
         assert_eq!(ship.position, (10, -8));
     }
 
     #[test]
     fn move_ship_2() {
         use Direction::*;
-        let mut ship = Ship::new();
-        ship.move_2(Move::new(Forward, 10));
+        let mut ship = Ship::<i64>::new();
+        ship.move_2(Move::new(Forward, 10)).unwrap();
         assert_eq!(ship.position, (100, 10));
-        ship.move_2(Move::new(North, 3));
+        ship.move_2(Move::new(North, 3)).unwrap();
         assert_eq!(ship.position, (100, 10));
         assert_eq!(ship.waypoint, (10, 4));
-        ship.move_2(Move::new(Forward, 7));
+        ship.move_2(Move::new(Forward, 7)).unwrap();
         assert_eq!(ship.position, (170, 38));
-        ship.move_2(Move::new(Right, 90));
+        ship.move_2(Move::new(Right, 90)).unwrap();
         assert_eq!(ship.position, (170, 38));
         assert_eq!(ship.waypoint, (4, -10));
-        ship.move_2(Move::new(Forward, 11));
+        ship.move_2(Move::new(Forward, 11)).unwrap();
         assert_eq!(ship.position, (214, -72));
-        ship.move_2(Move::new(Left, 90));
+        ship.move_2(Move::new(Left, 90)).unwrap();
         assert_eq!(ship.position, (214, -72));
         assert_eq!(ship.waypoint, (10, 4));
     }
+
+    #[test]
+    fn exact_rejects_odd_angles() {
+        use Direction::*;
+        let mut ship = Ship::<i64>::new();
+        assert_eq!(
+            ship.move_1(Move::new(Left, 45)),
+            Err(RotationError { degrees: 45 })
+        );
+        assert_eq!(ship.step, (1, 0));
+        assert_eq!(
+            ship.move_2(Move::new(Right, 30)),
+            Err(RotationError { degrees: 30 })
+        );
+        assert_eq!(ship.waypoint, (10, 1));
+        ship.move_2(Move::new(Right, 450)).unwrap();
+        assert_eq!(ship.waypoint, (1, -10));
+    }
+
+    #[test]
+    fn float_turns() {
+        use Direction::*;
+        let mut ship = Ship::<f64>::new();
+        ship.move_1(Move::new(Left, 45)).unwrap();
+        ship.move_1(Move::new(Forward, 2)).unwrap();
+        let (x, y) = ship.position;
+        assert!((x - 2f64.sqrt()).abs() < 1e-9 && (y - 2f64.sqrt()).abs() < 1e-9);
+        ship.move_1(Move::new(Right, 135)).unwrap();
+        let (dx, dy) = ship.step;
+        assert!((dx - 0.0).abs() < 1e-9 && (dy + 1.0).abs() < 1e-9);
+
+        let mut ship = Ship::<f64>::new();
+        ship.move_2(Move::new(Left, 30)).unwrap();
+        ship.move_2(Move::new(Left, 60)).unwrap();
+        let (wx, wy) = ship.waypoint;
+        assert!((wx + 1.0).abs() < 1e-9 && (wy - 10.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn float_matches_exact_on_quarter_turns() {
+        use Direction::*;
+        let moves = [
+            Move::new(Forward, 10),
+            Move::new(North, 3),
+            Move::new(Forward, 7),
+            Move::new(Right, 90),
+            Move::new(Forward, 11),
+            Move::new(Left, 270),
+            Move::new(West, 4),
+            Move::new(Right, 180),
+            Move::new(Forward, 5),
+        ];
+        let exact = record::<i64>(&moves, Ship::move_1).unwrap();
+        let float = record::<f64>(&moves, Ship::move_1).unwrap();
+        assert_eq!(float.final_distance(), exact.final_distance() as f64);
+        let exact = record::<i64>(&moves, Ship::move_2).unwrap();
+        let float = record::<f64>(&moves, Ship::move_2).unwrap();
+        assert_eq!(float.final_distance(), exact.final_distance() as f64);
+    }
+
+    #[test]
+    fn trajectory_stats() {
+        use Direction::*;
+        let moves = [
+            Move::new(Forward, 10),
+            Move::new(North, 3),
+            Move::new(Forward, 7),
+            Move::new(Right, 90),
+            Move::new(Forward, 11),
+        ];
+        let trajectory = record::<i64>(&moves, Ship::move_2).unwrap();
+        assert_eq!(
+            trajectory.positions,
+            vec![
+                (0, 0),
+                (100, 10),
+                (100, 10),
+                (170, 38),
+                (170, 38),
+                (214, -72)
+            ]
+        );
+        assert_eq!(trajectory.waypoints[3], (10, 4));
+        assert_eq!(trajectory.waypoints[4], (4, -10));
+        assert_eq!(trajectory.final_distance(), 286);
+        assert_eq!(trajectory.bounding_box(), ((0, -72), (214, 38)));
+        assert_eq!(trajectory.furthest_point(), (214, -72));
+        let expected = 10100f64.sqrt() + 5684f64.sqrt() + 14036f64.sqrt();
+        assert!((trajectory.distance_travelled() - expected).abs() < 1e-9);
+
+        let trajectory = record::<i64>(&moves, Ship::move_1).unwrap();
+        assert_eq!(trajectory.final_distance(), 25);
+        assert_eq!(trajectory.furthest_point(), (17, -8));
+        assert_eq!(trajectory.distance_travelled(), 31.0);
+    }
+
+    #[test]
+    fn svg_export() {
+        use Direction::*;
+        let moves = [Move::new(Forward, 2), Move::new(Right, 90)];
+        let trajectory = record::<i64>(&moves, Ship::move_2).unwrap();
+        let svg = trajectory.svg(true);
+        assert!(
+            svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"-1 -4 32 13\">")
+        );
+        assert!(svg.contains("<polyline points=\"0,0 20,-2 20,-2\""));
+        assert_eq!(svg.matches("<line ").count(), 3);
+        assert!(svg.contains("x1=\"20\" y1=\"-2\" x2=\"21\" y2=\"8\""));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(trajectory.svg(false).matches("<line ").count(), 0);
+    }
 }
@@ -1,3 +1,5 @@
+use std::env;
+use std::fmt;
 use std::ops::RangeInclusive;
 
 use parser::{fixed, take, take_while, take_while1, unsigned_number};
@@ -9,34 +11,224 @@ struct Policy {
     password: String,
 }
 
+#[derive(Debug, PartialEq)]
+enum Verdict {
+    Valid,
+    Invalid,
+    // Positions are 1-based and counted in chars
+    PositionOutOfRange { position: u64, length: usize },
+}
+
+impl Verdict {
+    fn from_bool(valid: bool) -> Self {
+        if valid {
+            Verdict::Valid
+        } else {
+            Verdict::Invalid
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        *self == Verdict::Valid
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::Valid => write!(f, "valid"),
+            Verdict::Invalid => write!(f, "invalid"),
+            Verdict::PositionOutOfRange { position, length } => write!(
+                f,
+                "position {} is outside a password of length {}",
+                position, length
+            ),
+        }
+    }
+}
+
 impl Policy {
-    fn validate_1(&self) -> bool {
-        let char_count = self
+    // Number of the two 1-based positions holding the policy character
+    fn position_matches(&self) -> Result<usize, Verdict> {
+        let chars: Vec<char> = self.password.chars().collect();
+        let mut matching = 0;
+        for &position in [*self.min_max.start(), *self.min_max.end()].iter() {
+            let c = position
+                .checked_sub(1)
+                .and_then(|i| chars.get(i as usize))
+                .ok_or(Verdict::PositionOutOfRange {
+                    position,
+                    length: chars.len(),
+                })?;
+            if *c == self.character {
+                matching += 1;
+            }
+        }
+        Ok(matching)
+    }
+
+    fn validate_positions(&self, pred: impl Fn(usize) -> bool) -> Verdict {
+        match self.position_matches() {
+            Ok(matching) => Verdict::from_bool(pred(matching)),
+            Err(verdict) => verdict,
+        }
+    }
+}
+
+trait PolicyRule {
+    fn name(&self) -> String;
+    fn validate(&self, policy: &Policy) -> Verdict;
+}
+
+struct CountRange;
+
+impl PolicyRule for CountRange {
+    fn name(&self) -> String {
+        "count".to_owned()
+    }
+
+    fn validate(&self, policy: &Policy) -> Verdict {
+        let char_count = policy
             .password
             .chars()
-            .filter(|c| *c == self.character)
+            .filter(|c| *c == policy.character)
             .count();
-        self.min_max.contains(&(char_count as u64))
+        Verdict::from_bool(policy.min_max.contains(&(char_count as u64)))
     }
+}
 
-    fn validate_2(&self) -> bool {
-        let first = *self.min_max.start() as usize;
-        let end = *self.min_max.end() as usize;
-        if self.password.len() < end as usize {
-            return false;
-        }
-        let mut matching = 0;
-        let mut chars = self.password.chars();
-        if chars.nth(first - 1).unwrap() == self.character {
-            matching += 1;
+struct ExactlyOnePosition;
+
+impl PolicyRule for ExactlyOnePosition {
+    fn name(&self) -> String {
+        "exactly-one-position".to_owned()
+    }
+
+    fn validate(&self, policy: &Policy) -> Verdict {
+        policy.validate_positions(|m| m == 1)
+    }
+}
+
+struct AtLeastOnePosition;
+
+impl PolicyRule for AtLeastOnePosition {
+    fn name(&self) -> String {
+        "at-least-one-position".to_owned()
+    }
+
+    fn validate(&self, policy: &Policy) -> Verdict {
+        policy.validate_positions(|m| m >= 1)
+    }
+}
+
+struct AtMostOnePosition;
+
+impl PolicyRule for AtMostOnePosition {
+    fn name(&self) -> String {
+        "at-most-one-position".to_owned()
+    }
+
+    fn validate(&self, policy: &Policy) -> Verdict {
+        policy.validate_positions(|m| m <= 1)
+    }
+}
+
+// A set of characters written like a regex class, e.g. "[a-f0-9_]" or "[^aeiou]"
+#[derive(Debug, PartialEq)]
+struct CharClass {
+    source: String,
+    negated: bool,
+    ranges: Vec<RangeInclusive<char>>,
+}
+
+impl CharClass {
+    fn parse(input: &str) -> Option<CharClass> {
+        let (_, inner) = fixed("[", input)?;
+        let inner = inner.strip_suffix(']')?;
+        let (negated, inner) = match inner.strip_prefix('^') {
+            Some(rest) => (true, rest),
+            None => (false, inner),
+        };
+        let chars: Vec<char> = inner.chars().collect();
+        if chars.is_empty() {
+            return None;
         }
-        if chars.nth(end - first - 1).unwrap() == self.character {
-            matching += 1;
+        let mut ranges = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            if i + 2 < chars.len() && chars[i + 1] == '-' {
+                if chars[i] > chars[i + 2] {
+                    return None;
+                }
+                ranges.push(chars[i]..=chars[i + 2]);
+                i += 3;
+            } else {
+                ranges.push(chars[i]..=chars[i]);
+                i += 1;
+            }
         }
-        return matching == 1;
+        Some(CharClass {
+            source: input.to_owned(),
+            negated,
+            ranges,
+        })
+    }
+
+    fn matches(&self, c: char) -> bool {
+        self.ranges.iter().any(|r| r.contains(&c)) != self.negated
+    }
+}
+
+struct ForbiddenCharacters(CharClass);
+
+impl PolicyRule for ForbiddenCharacters {
+    fn name(&self) -> String {
+        format!("forbidden:{}", self.0.source)
+    }
+
+    fn validate(&self, policy: &Policy) -> Verdict {
+        Verdict::from_bool(!policy.password.chars().any(|c| self.0.matches(c)))
+    }
+}
+
+struct OnlyCharacters(CharClass);
+
+impl PolicyRule for OnlyCharacters {
+    fn name(&self) -> String {
+        format!("only:{}", self.0.source)
+    }
+
+    fn validate(&self, policy: &Policy) -> Verdict {
+        Verdict::from_bool(policy.password.chars().all(|c| self.0.matches(c)))
     }
 }
 
+// Rules taking a character class are written as "name:[class]"
+fn rule_by_name(name: &str) -> Option<Box<dyn PolicyRule>> {
+    let (name, argument) = match name.find(':') {
+        Some(i) => (&name[..i], Some(&name[(i + 1)..])),
+        None => (name, None),
+    };
+    let rule: Box<dyn PolicyRule> = match (name, argument) {
+        ("count", None) => Box::new(CountRange),
+        ("exactly-one-position", None) => Box::new(ExactlyOnePosition),
+        ("at-least-one-position", None) => Box::new(AtLeastOnePosition),
+        ("at-most-one-position", None) => Box::new(AtMostOnePosition),
+        ("forbidden", Some(class)) => Box::new(ForbiddenCharacters(CharClass::parse(class)?)),
+        ("only", Some(class)) => Box::new(OnlyCharacters(CharClass::parse(class)?)),
+        _ => return None,
+    };
+    Some(rule)
+}
+
+fn violations(policy: &Policy, rules: &[Box<dyn PolicyRule>]) -> Vec<(String, Verdict)> {
+    rules
+        .iter()
+        .map(|rule| (rule.name(), rule.validate(policy)))
+        .filter(|(_, verdict)| !verdict.is_valid())
+        .collect()
+}
+
 fn single_char(input: &str) -> Option<(char, &str)> {
     let (c, input) = take(1, input)?;
     Some((c.chars().next()?, input))
@@ -51,10 +243,10 @@ fn parse_password_policy(input: &str) -> Option<(Policy, &str)> {
     let (_, input) = fixed(" ", input)?;
     let (character, input) = single_char(input)?;
     let (_, input) = fixed(": ", input)?;
-    let (password, input) = take_while1(|c| c.is_ascii_lowercase(), input)?;
+    let (password, input) = take_while1(|c| !c.is_whitespace(), input)?;
     let policy = Policy {
         min_max: first_num..=second_num,
-        character: character,
+        character,
         password: password.to_owned(),
     };
     Some((policy, input))
@@ -62,17 +254,39 @@ fn parse_password_policy(input: &str) -> Option<(Policy, &str)> {
 
 fn main() {
     let mut input = include_str!("input");
-    let mut valid_count_1 = 0;
-    let mut valid_count_2 = 0;
+    let mut policies = Vec::new();
     while let Some((policy, rest)) = parse_password_policy(input) {
-        if policy.validate_1() {
-            valid_count_1 += 1;
-        }
-        if policy.validate_2() {
-            valid_count_2 += 1;
-        }
+        policies.push(policy);
         input = rest;
     }
+
+    // Rules given as arguments get a report of every password breaking them
+    let rule_names: Vec<String> = env::args().skip(1).collect();
+    if !rule_names.is_empty() {
+        let rules = rule_names
+            .iter()
+            .map(|name| rule_by_name(name).unwrap_or_else(|| panic!("Unknown rule {}", name)))
+            .collect::<Vec<_>>();
+        for policy in policies.iter() {
+            let broken = violations(policy, &rules)
+                .iter()
+                .map(|(name, verdict)| format!("{} ({})", name, verdict))
+                .collect::<Vec<_>>();
+            if !broken.is_empty() {
+                println!("{}: {}", policy.password, broken.join(", "));
+            }
+        }
+        return;
+    }
+
+    let valid_count_1 = policies
+        .iter()
+        .filter(|p| CountRange.validate(p).is_valid())
+        .count();
+    let valid_count_2 = policies
+        .iter()
+        .filter(|p| ExactlyOnePosition.validate(p).is_valid())
+        .count();
     println!("Part 1: {} valid passwords", valid_count_1);
     println!("Part 2: {} valid passwords", valid_count_2);
 }
@@ -116,6 +330,17 @@ mod test {
         assert_eq!(res, None);
     }
 
+    #[test]
+    fn parse_ends_on_multibyte_char() {
+        assert_eq!(parse_password_policy("1-2 é"), None);
+        let expected = Policy {
+            min_max: 1..=2,
+            character: 'é',
+            password: "éé".to_owned(),
+        };
+        assert_eq!(parse_password_policy("1-2 é: éé"), Some((expected, "")));
+    }
+
     #[test]
     fn validate_1_valid() {
         let policy = Policy {
@@ -123,7 +348,7 @@ mod test {
             character: 'a',
             password: "abcde".to_owned(),
         };
-        assert!(policy.validate_1());
+        assert!(CountRange.validate(&policy).is_valid());
     }
 
     #[test]
@@ -133,7 +358,7 @@ mod test {
             character: 'b',
             password: "cdefg".to_owned(),
         };
-        assert!(!policy.validate_1());
+        assert!(!CountRange.validate(&policy).is_valid());
     }
 
     #[test]
@@ -143,7 +368,7 @@ mod test {
             character: 'a',
             password: "abcde".to_owned(),
         };
-        assert!(policy.validate_2());
+        assert!(ExactlyOnePosition.validate(&policy).is_valid());
     }
 
     #[test]
@@ -153,7 +378,7 @@ mod test {
             character: 'a',
             password: "cdefg".to_owned(),
         };
-        assert!(!policy.validate_2());
+        assert!(!ExactlyOnePosition.validate(&policy).is_valid());
     }
 
     #[test]
@@ -163,6 +388,133 @@ mod test {
             character: 'c',
             password: "ccccccccc".to_owned(),
         };
-        assert!(!policy.validate_2());
+        assert!(!ExactlyOnePosition.validate(&policy).is_valid());
+    }
+
+    #[test]
+    fn position_rules() {
+        let policy = Policy {
+            min_max: 1..=3,
+            character: 'c',
+            password: "ccccccccc".to_owned(),
+        };
+        assert!(AtLeastOnePosition.validate(&policy).is_valid());
+        assert!(!AtMostOnePosition.validate(&policy).is_valid());
+        let policy = Policy {
+            min_max: 1..=3,
+            character: 'a',
+            password: "cdefg".to_owned(),
+        };
+        assert!(!AtLeastOnePosition.validate(&policy).is_valid());
+        assert!(AtMostOnePosition.validate(&policy).is_valid());
+    }
+
+    #[test]
+    fn parse_char_class() {
+        let class = CharClass::parse("[a-cx]").unwrap();
+        assert_eq!(class.ranges, vec!['a'..='c', 'x'..='x']);
+        assert!(!class.negated);
+        assert!(class.matches('b') && class.matches('x') && !class.matches('d'));
+        let class = CharClass::parse("[^a-c-]").unwrap();
+        assert!(class.negated);
+        assert!(!class.matches('-') && class.matches('z'));
+        assert!(CharClass::parse("[]").is_none());
+        assert!(CharClass::parse("[z-a]").is_none());
+        assert!(CharClass::parse("a-z").is_none());
+    }
+
+    #[test]
+    fn rules_by_name() {
+        assert_eq!(rule_by_name("count").unwrap().name(), "count");
+        assert_eq!(
+            rule_by_name("forbidden:[xyz]").unwrap().name(),
+            "forbidden:[xyz]"
+        );
+        assert!(rule_by_name("forbidden").is_none());
+        assert!(rule_by_name("count:[a]").is_none());
+        assert!(rule_by_name("unknown").is_none());
+    }
+
+    #[test]
+    fn violation_report() {
+        let policy = Policy {
+            min_max: 1..=3,
+            character: 'b',
+            password: "cdefg".to_owned(),
+        };
+        let rules: Vec<_> = [
+            "count",
+            "exactly-one-position",
+            "forbidden:[e-f]",
+            "only:[a-g]",
+        ]
+        .iter()
+        .map(|name| rule_by_name(name).unwrap())
+        .collect();
+        let names: Vec<_> = violations(&policy, &rules)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(
+            names,
+            vec!["count", "exactly-one-position", "forbidden:[e-f]"]
+        );
+    }
+
+    #[test]
+    fn parse_unicode_password() {
+        let input = "1-2 é: aé€\n";
+        let expected = Policy {
+            min_max: 1..=2,
+            character: 'é',
+            password: "aé€".to_owned(),
+        };
+        assert_eq!(parse_password_policy(input), Some((expected, "\n")));
+    }
+
+    #[test]
+    fn validate_positions_unicode() {
+        // Byte lengths would put position 3 past the end of "aé€"
+        let policy = Policy {
+            min_max: 2..=3,
+            character: 'é',
+            password: "aé€".to_owned(),
+        };
+        assert_eq!(ExactlyOnePosition.validate(&policy), Verdict::Valid);
+        let policy = Policy {
+            min_max: 1..=3,
+            character: 'é',
+            password: "éaé".to_owned(),
+        };
+        assert_eq!(ExactlyOnePosition.validate(&policy), Verdict::Invalid);
+        assert_eq!(CountRange.validate(&policy), Verdict::Valid);
+    }
+
+    #[test]
+    fn validate_positions_out_of_range() {
+        let policy = Policy {
+            min_max: 2..=5,
+            character: 'é',
+            password: "aé€".to_owned(),
+        };
+        assert_eq!(
+            ExactlyOnePosition.validate(&policy),
+            Verdict::PositionOutOfRange {
+                position: 5,
+                length: 3
+            }
+        );
+        let policy = Policy {
+            min_max: 0..=1,
+            character: 'a',
+            password: "abc".to_owned(),
+        };
+        assert_eq!(
+            AtLeastOnePosition.validate(&policy),
+            Verdict::PositionOutOfRange {
+                position: 0,
+                length: 3
+            }
+        );
     }
 }
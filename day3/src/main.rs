@@ -1,3 +1,12 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::ops::RangeInclusive;
+
+use parser::{grid, GridError};
+
+type Slope = (usize, usize);
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 enum Tile {
     Tree,
@@ -11,31 +20,23 @@ struct Map {
 }
 
 impl Map {
-    fn parse(map_str: &str) -> Self {
-        let mut tiles = Vec::with_capacity(map_str.len());
-        let mut width: Option<usize> = None;
-        for (i, ch) in map_str.chars().enumerate() {
-            let tile = match ch {
-                '.' => Tile::Open,
-                '#' => Tile::Tree,
-                c if c.is_ascii_whitespace() => {
-                    let _ = width.get_or_insert(i);
-                    continue;
-                }
-                u @ _ => panic!("Unexpected input {}", u),
-            };
-            tiles.push(tile);
-        }
-        Map {
-            tiles: tiles,
-            width: width.expect("No row break encountered"),
-        }
+    fn parse(map_str: &str) -> Result<Self, GridError> {
+        let (tiles, width) = grid(map_str, |c| match c {
+            '.' => Some(Tile::Open),
+            '#' => Some(Tile::Tree),
+            _ => None,
+        })?;
+        Ok(Map { tiles, width })
     }
 
     fn get_tile(&self, x: usize, y: usize) -> Option<Tile> {
         let wrapped_x = x % self.width;
         self.tiles.get(wrapped_x + y * self.width).copied()
     }
+
+    fn height(&self) -> usize {
+        self.tiles.len() / self.width
+    }
 }
 
 fn count_trees(x_incr: usize, y_incr: usize, map: &Map) -> u64 {
@@ -51,20 +52,105 @@ fn count_trees(x_incr: usize, y_incr: usize, map: &Map) -> u64 {
     trees
 }
 
+// Walks the map once per distinct row step, checking every slope sharing that step on each row
+fn count_trees_batch(slopes: &[Slope], map: &Map) -> Vec<u64> {
+    let mut by_y_incr: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (i, &(_, y_incr)) in slopes.iter().enumerate() {
+        by_y_incr.entry(y_incr).or_default().push(i);
+    }
+
+    let mut counts = vec![0; slopes.len()];
+    for (y_incr, indices) in by_y_incr {
+        assert!(y_incr > 0, "Slope must move down");
+        for (step, y) in (0..map.height()).step_by(y_incr).enumerate() {
+            for &i in indices.iter() {
+                let (x_incr, _) = slopes[i];
+                if map.get_tile(step * x_incr, y) == Some(Tile::Tree) {
+                    counts[i] += 1;
+                }
+            }
+        }
+    }
+    counts
+}
+
+fn all_slopes(x_incrs: RangeInclusive<usize>, y_incrs: RangeInclusive<usize>) -> Vec<Slope> {
+    y_incrs
+        .filter(|&y_incr| y_incr > 0)
+        .flat_map(|y_incr| x_incrs.clone().map(move |x_incr| (x_incr, y_incr)))
+        .collect()
+}
+
+// Fewest trees first, ties broken by slope
+fn rank_slopes(slopes: &[Slope], map: &Map) -> Vec<(Slope, u64)> {
+    let counts = count_trees_batch(slopes, map);
+    let mut ranked: Vec<(Slope, u64)> = slopes.iter().copied().zip(counts).collect();
+    ranked.sort_by_key(|&(slope, trees)| (trees, slope));
+    ranked
+}
+
+fn min_trees_slope(slopes: &[Slope], map: &Map) -> Option<(Slope, u64)> {
+    rank_slopes(slopes, map).into_iter().next()
+}
+
+fn trees_product(slopes: &[Slope], map: &Map) -> u64 {
+    count_trees_batch(slopes, map).iter().product()
+}
+
+// Marks the path like the puzzle text does, leaving the starting square unmarked and repeating
+// the pattern to the right as far as the path goes
+fn render_path((x_incr, y_incr): Slope, map: &Map) -> String {
+    assert!(y_incr > 0, "Slope must move down");
+    let height = map.height();
+    let furthest_x = (height - 1) / y_incr * x_incr;
+    let columns = (furthest_x / map.width + 1) * map.width;
+
+    let mut rendered = String::with_capacity((columns + 1) * height);
+    for y in 0..height {
+        let on_path = y > 0 && y % y_incr == 0;
+        let path_x = y / y_incr * x_incr;
+        for x in 0..columns {
+            let tile = map.get_tile(x, y).unwrap();
+            let ch = match (tile, on_path && x == path_x) {
+                (Tile::Tree, true) => 'X',
+                (Tile::Open, true) => 'O',
+                (Tile::Tree, false) => '#',
+                (Tile::Open, false) => '.',
+            };
+            rendered.push(ch);
+        }
+        rendered.push('\n');
+    }
+    rendered
+}
+
 fn main() {
     let input = include_str!("input");
-    let map = Map::parse(input);
+    let map = Map::parse(input).expect("Failed to parse map");
 
     let first_count = count_trees(3, 1, &map);
     println!("Part 1: {} trees hit", first_count);
 
-    let mut product: u64 = [(1, 1), (5, 1), (7, 1), (1, 2)]
-        .iter()
-        .map(|(x_incr, y_incr)| count_trees(*x_incr, *y_incr, &map))
-        .product();
-
-    product *= first_count;
+    let product = trees_product(&[(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)], &map);
     println!("Part 2: {} trees hit", product);
+
+    let slopes = all_slopes(1..=10, 1..=3);
+    if let Some(((x_incr, y_incr), trees)) = min_trees_slope(&slopes, &map) {
+        println!(
+            "Fewest trees: {} with right {}, down {}",
+            trees, x_incr, y_incr
+        );
+    }
+
+    // Pass "-" to draw the part 1 path in the terminal, or a file name to write it there
+    if let Some(target) = env::args().nth(1) {
+        let rendered = render_path((3, 1), &map);
+        if target == "-" {
+            print!("{}", rendered);
+        } else {
+            fs::write(&target, rendered).expect("Failed to write path");
+        }
+    }
 }
 
 #[cfg(test)]
@@ -79,7 +165,38 @@ mod test {
             tiles: vec![Tile::Open, Tile::Tree, Tile::Tree, Tile::Open],
             width: 2,
         };
-        assert_eq!(map, expected);
+        assert_eq!(map, Ok(expected));
+    }
+
+    #[test]
+    fn parse_input_crlf() {
+        let input = ".#\r\n#.\r\n";
+        let map = Map::parse(input);
+        let expected = Map {
+            tiles: vec![Tile::Open, Tile::Tree, Tile::Tree, Tile::Open],
+            width: 2,
+        };
+        assert_eq!(map, Ok(expected));
+    }
+
+    #[test]
+    fn parse_input_errors() {
+        assert_eq!(
+            Map::parse(".#\n#O"),
+            Err(GridError::UnexpectedChar {
+                row: 2,
+                column: 2,
+                found: 'O'
+            })
+        );
+        assert_eq!(
+            Map::parse(".#\n#.#"),
+            Err(GridError::RaggedRow {
+                row: 2,
+                expected: 2,
+                found: 3
+            })
+        );
     }
 
     #[test]
@@ -108,4 +225,78 @@ mod test {
         };
         assert_eq!(map.get_tile(0, 2), None);
     }
+
+    fn example_map() -> Map {
+        let input = "..##.......\n#...#...#..\n.#....#..#.\n..#.#...#.#\n.#...##..#.\n..#.##.....\n.#.#.#....#\n.#........#\n#.##...#...\n#...##....#\n.#..#...#.#";
+        Map::parse(input).unwrap()
+    }
+
+    #[test]
+    fn batch_matches_single() {
+        let map = example_map();
+        let slopes = all_slopes(0..=12, 0..=12);
+        let counts = count_trees_batch(&slopes, &map);
+        for (&(x_incr, y_incr), &trees) in slopes.iter().zip(counts.iter()) {
+            assert_eq!(trees, count_trees(x_incr, y_incr, &map));
+        }
+    }
+
+    #[test]
+    fn product_of_example_slopes() {
+        let map = example_map();
+        let slopes = [(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)];
+        assert_eq!(count_trees_batch(&slopes, &map), vec![2, 7, 3, 4, 2]);
+        assert_eq!(trees_product(&slopes, &map), 336);
+    }
+
+    #[test]
+    fn ranked_slopes() {
+        let map = example_map();
+        let slopes = [(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)];
+        assert_eq!(
+            rank_slopes(&slopes, &map),
+            vec![
+                ((1, 1), 2),
+                ((1, 2), 2),
+                ((5, 1), 3),
+                ((7, 1), 4),
+                ((3, 1), 7)
+            ]
+        );
+        assert_eq!(min_trees_slope(&slopes, &map), Some(((1, 1), 2)));
+        assert_eq!(min_trees_slope(&[], &map), None);
+    }
+
+    #[test]
+    fn render_example_path() {
+        let map = example_map();
+        let expected = "\
+..##.........##.......
+#..O#...#..#...#...#..
+.#....X..#..#....#..#.
+..#.#...#O#..#.#...#.#
+.#...##..#..X...##..#.
+..#.##.......#.X#.....
+.#.#.#....#.#.#.#.O..#
+.#........#.#........X
+#.##...#...#.##...#...
+#...##....##...##....#
+.#..#...#.#.#..#...#.#
+";
+        let rendered = render_path((3, 1), &map);
+        assert!(rendered.lines().all(|l| l.len() == 33));
+        let cropped: String = rendered
+            .lines()
+            .map(|l| format!("{}\n", &l[..22]))
+            .collect();
+        assert_eq!(cropped, expected);
+        assert_eq!(rendered.matches('X').count(), 7);
+        assert_eq!(rendered.matches('O').count(), 3);
+    }
+
+    #[test]
+    fn render_skipped_rows() {
+        let map = Map::parse(".#\n#.\n..").unwrap();
+        assert_eq!(render_path((1, 2), &map), ".#\n#.\n.O\n");
+    }
 }
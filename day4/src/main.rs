@@ -1,32 +1,27 @@
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
 
-use parser::{fixed, match_n, optional, take_while1, unsigned_number};
+use parser::{eof, fixed, key_value_record, match_n, take_while1, unsigned_number};
 
-fn identifier(input: &str) -> Option<(&str, &str)> {
-    take_while1(|c| !c.is_whitespace() && c != ':', input)
-}
-
-fn blank_line(input: &str) -> Option<(&str, &str)> {
-    match_n(|c| c == '\n', 2, input)
-}
-
-fn field(input: &str) -> Option<((&str, &str), &str)> {
-    let (_, input) = optional(|inp| match_n(|c| c.is_whitespace(), 1, inp), input);
-    let (key, input) = identifier(input)?;
-    let (_, input) = fixed(":", input)?;
-    let (value, input) = identifier(input)?;
-    Some(((key, value), input))
-}
+const PASSPORT_SCHEMA: &str = "\
+byr required year 1920-2002
+iyr required year 2010-2020
+eyr required year 2020-2030
+hgt required measurement cm:150-193 in:59-76
+hcl required hexcolor
+ecl required oneof amb blu brn gry grn hzl oth
+pid required digits 9
+cid optional any
+";
 
 fn passport(input: &str) -> Option<(HashMap<String, String>, &str)> {
-    let mut map = HashMap::new();
-    let ((key, val), mut input) = field(input)?;
-    map.insert(key.to_owned(), val.to_owned());
-    while let Some(((key, val), new_input)) = field(input) {
-        map.insert(key.to_owned(), val.to_owned());
-        input = new_input;
-    }
-    let (_, input) = optional(blank_line, input);
+    let (fields, input) = key_value_record(input)?;
+    let map = fields
+        .into_iter()
+        .map(|(key, val)| (key.to_owned(), val.to_owned()))
+        .collect();
     Some((map, input))
 }
 
@@ -40,16 +35,21 @@ fn passports(input: &str) -> Vec<HashMap<String, String>> {
     passports
 }
 
-fn validate_passport_1(passport: &HashMap<String, String>) -> bool {
-    for key in &["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"] {
-        if !passport.contains_key(*key) {
-            return false;
-        }
-    }
-    true
+// Loose is how the puzzle was originally solved, Strict follows the spec to the letter
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Mode {
+    Loose,
+    Strict,
+}
+
+fn all_digits(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_digit())
 }
 
-fn validate_number(num_str: &str, min: u32, max: u32) -> bool {
+fn validate_number(num_str: &str, min: u32, max: u32, mode: Mode) -> bool {
+    if mode == Mode::Strict && !all_digits(num_str) {
+        return false;
+    }
     if let Ok(num) = num_str.parse::<u32>() {
         num >= min && num <= max
     } else {
@@ -57,62 +57,485 @@ fn validate_number(num_str: &str, min: u32, max: u32) -> bool {
     }
 }
 
-fn validate_hgt(hgt_str: &str) -> bool {
-    if hgt_str.ends_with("cm") {
-        validate_number(&hgt_str[..hgt_str.len() - 2], 150, 193)
-    } else if hgt_str.ends_with("in") {
-        validate_number(&hgt_str[..hgt_str.len() - 2], 59, 76)
-    } else {
-        false
-    }
+fn validate_year(year_str: &str, min: u32, max: u32, mode: Mode) -> bool {
+    (mode == Mode::Loose || year_str.len() == 4) && validate_number(year_str, min, max, mode)
 }
 
-fn hex_color(input: &str) -> Option<(&str, &str)> {
+fn hex_color(input: &str, mode: Mode) -> Option<(&str, &str)> {
     let (_, input) = fixed("#", input)?;
     match_n(
-        // Technically incorrect since it also matches uppercase A-F, but meh...
-        |c| c.is_ascii_hexdigit(),
+        |c| match mode {
+            Mode::Loose => c.is_ascii_hexdigit(),
+            Mode::Strict => c.is_ascii_digit() || ('a'..='f').contains(&c),
+        },
         6,
         input,
     )
 }
 
-fn validate_ecl(ecl_str: &str) -> bool {
-    match ecl_str {
-        "amb" | "blu" | "brn" | "gry" | "grn" | "hzl" | "oth" => true,
-        _ => false,
+fn validate_digits(digits_str: &str, length: usize, mode: Mode) -> bool {
+    digits_str.len() == length
+        && match mode {
+            Mode::Loose => unsigned_number(digits_str).is_some(),
+            Mode::Strict => all_digits(digits_str),
+        }
+}
+
+#[derive(Debug, PartialEq)]
+enum Validator {
+    Any,
+    IntRange(RangeInclusive<u32>),
+    Year(RangeInclusive<u32>),
+    Measurement(Vec<(String, RangeInclusive<u32>)>),
+    HexColor,
+    OneOf(Vec<String>),
+    Digits(usize),
+}
+
+impl Validator {
+    fn parse(kind: &str, args: &[&str]) -> Option<Validator> {
+        let validator = match (kind, args) {
+            ("any", []) => Validator::Any,
+            ("int", [range]) => Validator::IntRange(parse_range(range)?),
+            ("year", [range]) => Validator::Year(parse_range(range)?),
+            ("measurement", units) if !units.is_empty() => {
+                let mut ranges = Vec::new();
+                for unit in units.iter() {
+                    let (unit, range) = take_while1(|c| c != ':', unit)?;
+                    let (_, range) = fixed(":", range)?;
+                    ranges.push((unit.to_owned(), parse_range(range)?));
+                }
+                Validator::Measurement(ranges)
+            }
+            ("hexcolor", []) => Validator::HexColor,
+            ("oneof", values) if !values.is_empty() => {
+                Validator::OneOf(values.iter().map(|&v| v.to_owned()).collect())
+            }
+            ("digits", [length]) => Validator::Digits(length.parse().ok()?),
+            _ => return None,
+        };
+        Some(validator)
+    }
+
+    fn check(&self, value: &str, mode: Mode) -> Result<(), String> {
+        let in_range = |num_str: &str, range: &RangeInclusive<u32>| {
+            if validate_number(num_str, *range.start(), *range.end(), mode) {
+                Ok(())
+            } else {
+                Err(format!(
+                    "{} is not a number in {}-{}",
+                    num_str,
+                    range.start(),
+                    range.end()
+                ))
+            }
+        };
+        match self {
+            Validator::Any => Ok(()),
+            Validator::IntRange(range) => in_range(value, range),
+            Validator::Year(range) => {
+                if validate_year(value, *range.start(), *range.end(), mode) {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "{} is not a year in {}-{}",
+                        value,
+                        range.start(),
+                        range.end()
+                    ))
+                }
+            }
+            Validator::Measurement(units) => units
+                .iter()
+                .find_map(|(unit, range)| Some((value.strip_suffix(unit.as_str())?, range)))
+                .map_or_else(
+                    || Err(format!("{} has no known unit", value)),
+                    |(num_str, range)| in_range(num_str, range),
+                ),
+            Validator::HexColor => match hex_color(value, mode) {
+                Some((_, "")) => Ok(()),
+                _ => Err(format!("{} is not a hex color", value)),
+            },
+            Validator::OneOf(values) => {
+                if values.iter().any(|v| v == value) {
+                    Ok(())
+                } else {
+                    Err(format!("{} is not one of {}", value, values.join(", ")))
+                }
+            }
+            Validator::Digits(length) => {
+                if validate_digits(value, *length, mode) {
+                    Ok(())
+                } else {
+                    Err(format!("{} is not a {} digit number", value, length))
+                }
+            }
+        }
+    }
+}
+
+fn parse_range(input: &str) -> Option<RangeInclusive<u32>> {
+    let (min, input) = unsigned_number(input)?;
+    let (_, input) = fixed("-", input)?;
+    let (max, input) = unsigned_number(input)?;
+    eof(input)?;
+    Some(u32::try_from(min).ok()?..=u32::try_from(max).ok()?)
+}
+
+#[derive(Debug, PartialEq)]
+struct FieldSpec {
+    key: String,
+    required: bool,
+    validator: Validator,
+}
+
+#[derive(Debug, PartialEq)]
+struct FieldError {
+    key: String,
+    reason: String,
+}
+
+// One field per line: "<key> required|optional <validator> [args...]"
+#[derive(Debug, PartialEq)]
+struct Schema {
+    fields: Vec<FieldSpec>,
+}
+
+impl Schema {
+    fn parse(input: &str) -> Option<Schema> {
+        let mut fields = Vec::new();
+        for line in input.lines().filter(|l| !l.trim().is_empty()) {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let (key, required, kind, args) = match words.as_slice() {
+                [key, "required", kind, args @ ..] => (key, true, kind, args),
+                [key, "optional", kind, args @ ..] => (key, false, kind, args),
+                _ => return None,
+            };
+            fields.push(FieldSpec {
+                key: (*key).to_owned(),
+                required,
+                validator: Validator::parse(kind, args)?,
+            });
+        }
+        Some(Schema { fields })
+    }
+
+    fn validate(&self, passport: &HashMap<String, String>, mode: Mode) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        for spec in self.fields.iter() {
+            let reason = match passport.get(&spec.key) {
+                Some(value) => spec.validator.check(value, mode).err(),
+                None if spec.required => Some("missing".to_owned()),
+                None => None,
+            };
+            if let Some(reason) = reason {
+                errors.push(FieldError {
+                    key: spec.key.clone(),
+                    reason,
+                });
+            }
+        }
+        errors
+    }
+}
+
+// The typed fields always follow the spec strictly
+fn parse_in_range(num_str: &str, min: u32, max: u32) -> Result<u32, String> {
+    if validate_number(num_str, min, max, Mode::Strict) {
+        Ok(num_str.parse().unwrap())
+    } else {
+        Err(format!("{} is not a number in {}-{}", num_str, min, max))
+    }
+}
+
+fn parse_year(year_str: &str, min: u32, max: u32) -> Result<u32, String> {
+    if validate_year(year_str, min, max, Mode::Strict) {
+        Ok(year_str.parse().unwrap())
+    } else {
+        Err(format!("{} is not a year in {}-{}", year_str, min, max))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct BirthYear(u32);
+
+impl FromStr for BirthYear {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        parse_year(s, 1920, 2002).map(BirthYear)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct IssueYear(u32);
+
+impl FromStr for IssueYear {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        parse_year(s, 2010, 2020).map(IssueYear)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct ExpirationYear(u32);
+
+impl FromStr for ExpirationYear {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        parse_year(s, 2020, 2030).map(ExpirationYear)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum HeightUnit {
+    Cm,
+    In,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Height {
+    value: u32,
+    unit: HeightUnit,
+}
+
+impl FromStr for Height {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        if let Some(cm) = s.strip_suffix("cm") {
+            let value = parse_in_range(cm, 150, 193)?;
+            Ok(Height {
+                value,
+                unit: HeightUnit::Cm,
+            })
+        } else if let Some(inches) = s.strip_suffix("in") {
+            let value = parse_in_range(inches, 59, 76)?;
+            Ok(Height {
+                value,
+                unit: HeightUnit::In,
+            })
+        } else {
+            Err(format!("{} has no known unit", s))
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct HairColor([u8; 3]);
+
+impl FromStr for HairColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match hex_color(s, Mode::Strict) {
+            Some((hex, "")) => {
+                let mut rgb = [0; 3];
+                for (i, channel) in rgb.iter_mut().enumerate() {
+                    *channel = u8::from_str_radix(&hex[(i * 2)..(i * 2 + 2)], 16).unwrap();
+                }
+                Ok(HairColor(rgb))
+            }
+            _ => Err(format!("{} is not a hex color", s)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum EyeColor {
+    Amber,
+    Blue,
+    Brown,
+    Gray,
+    Green,
+    Hazel,
+    Other,
+}
+
+impl FromStr for EyeColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let color = match s {
+            "amb" => EyeColor::Amber,
+            "blu" => EyeColor::Blue,
+            "brn" => EyeColor::Brown,
+            "gry" => EyeColor::Gray,
+            "grn" => EyeColor::Green,
+            "hzl" => EyeColor::Hazel,
+            "oth" => EyeColor::Other,
+            _ => return Err(format!("{} is not a known eye color", s)),
+        };
+        Ok(color)
+    }
+}
+
+// Kept as a string since leading zeros are significant
+#[derive(Debug, PartialEq, Clone)]
+struct PassportId(String);
+
+impl FromStr for PassportId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        if validate_digits(s, 9, Mode::Strict) {
+            Ok(PassportId(s.to_owned()))
+        } else {
+            Err(format!("{} is not a 9 digit number", s))
+        }
     }
 }
 
-fn validate_pid(pid_str: &str) -> bool {
-    pid_str.len() == 9 && unsigned_number(pid_str).is_some()
+#[derive(Debug, PartialEq)]
+struct Passport {
+    birth_year: BirthYear,
+    issue_year: IssueYear,
+    expiration_year: ExpirationYear,
+    height: Height,
+    hair_color: HairColor,
+    eye_color: EyeColor,
+    passport_id: PassportId,
+    country_id: Option<String>,
 }
 
-fn validate_with(
-    passport: &HashMap<String, String>,
+fn typed_field<T: FromStr<Err = String>>(
+    fields: &HashMap<String, String>,
     key: &str,
-    pred: impl Fn(&str) -> bool,
-) -> bool {
-    passport.get(key).map(|s| pred(s)).unwrap_or(false)
+    errors: &mut Vec<FieldError>,
+) -> Option<T> {
+    let reason = match fields.get(key) {
+        Some(value) => match value.parse() {
+            Ok(parsed) => return Some(parsed),
+            Err(reason) => reason,
+        },
+        None => "missing".to_owned(),
+    };
+    errors.push(FieldError {
+        key: key.to_owned(),
+        reason,
+    });
+    None
+}
+
+impl TryFrom<&HashMap<String, String>> for Passport {
+    type Error = Vec<FieldError>;
+
+    fn try_from(fields: &HashMap<String, String>) -> Result<Self, Self::Error> {
+        let mut errors = Vec::new();
+        let birth_year = typed_field(fields, "byr", &mut errors);
+        let issue_year = typed_field(fields, "iyr", &mut errors);
+        let expiration_year = typed_field(fields, "eyr", &mut errors);
+        let height = typed_field(fields, "hgt", &mut errors);
+        let hair_color = typed_field(fields, "hcl", &mut errors);
+        let eye_color = typed_field(fields, "ecl", &mut errors);
+        let passport_id = typed_field(fields, "pid", &mut errors);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(Passport {
+            birth_year: birth_year.unwrap(),
+            issue_year: issue_year.unwrap(),
+            expiration_year: expiration_year.unwrap(),
+            height: height.unwrap(),
+            hair_color: hair_color.unwrap(),
+            eye_color: eye_color.unwrap(),
+            passport_id: passport_id.unwrap(),
+            country_id: fields.get("cid").cloned(),
+        })
+    }
+}
+
+// Part 1 only needs the fields to be present, so values that don't parse are kept as written
+#[derive(Debug, PartialEq)]
+enum Lenient<T> {
+    Parsed(T),
+    Raw(String),
 }
 
-fn validate_passport_2(passport: &HashMap<String, String>) -> bool {
-    validate_with(passport, "byr", |s| validate_number(&s, 1920, 2002))
-        && validate_with(passport, "iyr", |s| validate_number(&s, 2010, 2020))
-        && validate_with(passport, "eyr", |s| validate_number(&s, 2020, 2030))
-        && validate_with(passport, "hgt", validate_hgt)
-        && validate_with(passport, "hcl", |s| hex_color(s).is_some())
-        && validate_with(passport, "ecl", validate_ecl)
-        && validate_with(passport, "pid", validate_pid)
+#[derive(Debug, PartialEq)]
+struct LenientPassport {
+    birth_year: Lenient<BirthYear>,
+    issue_year: Lenient<IssueYear>,
+    expiration_year: Lenient<ExpirationYear>,
+    height: Lenient<Height>,
+    hair_color: Lenient<HairColor>,
+    eye_color: Lenient<EyeColor>,
+    passport_id: Lenient<PassportId>,
+    country_id: Option<String>,
+}
+
+fn lenient_field<T: FromStr>(
+    fields: &HashMap<String, String>,
+    key: &str,
+    errors: &mut Vec<FieldError>,
+) -> Option<Lenient<T>> {
+    match fields.get(key) {
+        Some(value) => Some(
+            value
+                .parse()
+                .map_or_else(|_| Lenient::Raw(value.clone()), Lenient::Parsed),
+        ),
+        None => {
+            errors.push(FieldError {
+                key: key.to_owned(),
+                reason: "missing".to_owned(),
+            });
+            None
+        }
+    }
+}
+
+impl TryFrom<&HashMap<String, String>> for LenientPassport {
+    type Error = Vec<FieldError>;
+
+    fn try_from(fields: &HashMap<String, String>) -> Result<Self, Self::Error> {
+        let mut errors = Vec::new();
+        let birth_year = lenient_field(fields, "byr", &mut errors);
+        let issue_year = lenient_field(fields, "iyr", &mut errors);
+        let expiration_year = lenient_field(fields, "eyr", &mut errors);
+        let height = lenient_field(fields, "hgt", &mut errors);
+        let hair_color = lenient_field(fields, "hcl", &mut errors);
+        let eye_color = lenient_field(fields, "ecl", &mut errors);
+        let passport_id = lenient_field(fields, "pid", &mut errors);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(LenientPassport {
+            birth_year: birth_year.unwrap(),
+            issue_year: issue_year.unwrap(),
+            expiration_year: expiration_year.unwrap(),
+            height: height.unwrap(),
+            hair_color: hair_color.unwrap(),
+            eye_color: eye_color.unwrap(),
+            passport_id: passport_id.unwrap(),
+            country_id: fields.get("cid").cloned(),
+        })
+    }
 }
 
 fn main() {
     let input = include_str!("input");
     let passports = passports(input);
-    let valid_count_1 = passports.iter().filter(|&p| validate_passport_1(p)).count();
-    println!("Part 1: {} valid passports", valid_count_1);
-    let valid_count_2 = passports.iter().filter(|&p| validate_passport_2(p)).count();
-    println!("Part 2: {} valid passports", valid_count_2);
+    let lenient: Vec<LenientPassport> = passports
+        .iter()
+        .filter_map(|p| LenientPassport::try_from(p).ok())
+        .collect();
+    println!("Part 1: {} valid passports", lenient.len());
+    let valid_count_2 = passports
+        .iter()
+        .filter(|&p| Passport::try_from(p).is_ok())
+        .count();
+    let schema = Schema::parse(PASSPORT_SCHEMA).expect("Invalid schema");
+    let loose_count = passports
+        .iter()
+        .filter(|&p| schema.validate(p, Mode::Loose).is_empty())
+        .count();
+    println!(
+        "Part 2: {} valid passports ({} with loose validation)",
+        valid_count_2, loose_count
+    );
 }
 
 #[cfg(test)]
@@ -120,10 +543,15 @@ mod test {
     use super::*;
 
     #[test]
-    fn field_complete() {
-        let input = "hcl:#341e13";
-        let res = field(input);
-        assert_eq!(res, Some((("hcl", "#341e13"), "")));
+    fn passports_crlf() {
+        let input = "hcl:#341e13\r\neyr:2024 \r\n\r\nhgt:179cm\r\n";
+        let res = passports(input);
+        let mut expected_1 = HashMap::new();
+        expected_1.insert("hcl".to_owned(), "#341e13".to_owned());
+        expected_1.insert("eyr".to_owned(), "2024".to_owned());
+        let mut expected_2 = HashMap::new();
+        expected_2.insert("hgt".to_owned(), "179cm".to_owned());
+        assert_eq!(res, vec![expected_1, expected_2]);
     }
 
     #[test]
@@ -157,4 +585,276 @@ mod test {
         expected_2.insert("hgt".to_owned(), "179cm".to_owned());
         assert_eq!(res, vec![expected_1, expected_2]);
     }
+
+    fn passport_from(fields: &str) -> HashMap<String, String> {
+        passport(fields).unwrap().0
+    }
+
+    impl LenientPassport {
+        fn is_strictly_valid(&self) -> bool {
+            matches!(
+                self,
+                LenientPassport {
+                    birth_year: Lenient::Parsed(_),
+                    issue_year: Lenient::Parsed(_),
+                    expiration_year: Lenient::Parsed(_),
+                    height: Lenient::Parsed(_),
+                    hair_color: Lenient::Parsed(_),
+                    eye_color: Lenient::Parsed(_),
+                    passport_id: Lenient::Parsed(_),
+                    ..
+                }
+            )
+        }
+    }
+
+    #[test]
+    fn parse_schema() {
+        let schema =
+            Schema::parse("hgt required measurement cm:150-193 in:59-76\ncid optional any\n");
+        let expected = Schema {
+            fields: vec![
+                FieldSpec {
+                    key: "hgt".to_owned(),
+                    required: true,
+                    validator: Validator::Measurement(vec![
+                        ("cm".to_owned(), 150..=193),
+                        ("in".to_owned(), 59..=76),
+                    ]),
+                },
+                FieldSpec {
+                    key: "cid".to_owned(),
+                    required: false,
+                    validator: Validator::Any,
+                },
+            ],
+        };
+        assert_eq!(schema, Some(expected));
+    }
+
+    #[test]
+    fn parse_schema_invalid() {
+        assert!(Schema::parse("byr sometimes int 1-2").is_none());
+        assert!(Schema::parse("byr required int 1-").is_none());
+        assert!(Schema::parse("byr required hexcolor 6").is_none());
+        assert!(Schema::parse("byr required oneof").is_none());
+        assert!(Schema::parse("byr required unknown").is_none());
+        assert!(Schema::parse("byr required int 0-5000000000").is_none());
+        assert!(Schema::parse("hgt required measurement cm:4294967296-1").is_none());
+    }
+
+    #[test]
+    fn schema_valid_passports() {
+        let schema = Schema::parse(PASSPORT_SCHEMA).unwrap();
+        let valid = [
+            "pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980 hcl:#623a2f",
+            "eyr:2029 ecl:blu cid:129 byr:1989 iyr:2014 pid:896056539 hcl:#a97842 hgt:165cm",
+            "hcl:#888785 hgt:164cm byr:2001 iyr:2015 cid:88 pid:545766238 ecl:hzl eyr:2022",
+            "iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719",
+        ];
+        for p in valid.iter() {
+            assert_eq!(schema.validate(&passport_from(p), Mode::Strict), vec![]);
+        }
+    }
+
+    #[test]
+    fn schema_reports_every_failure() {
+        let schema = Schema::parse(PASSPORT_SCHEMA).unwrap();
+        let p = passport_from("eyr:1972 cid:100 hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018");
+        let failing: Vec<_> = schema
+            .validate(&p, Mode::Strict)
+            .into_iter()
+            .map(|e| e.key)
+            .collect();
+        assert_eq!(failing, vec!["byr", "eyr", "hgt", "pid"]);
+
+        let p = passport_from(
+            "hcl:dab227 iyr:2012 ecl:brn hgt:182cm pid:021572410 eyr:2020 byr:1992 cid:277",
+        );
+        assert_eq!(
+            schema.validate(&p, Mode::Strict),
+            vec![FieldError {
+                key: "hcl".to_owned(),
+                reason: "dab227 is not a hex color".to_owned()
+            }]
+        );
+
+        let p = passport_from(
+            "iyr:2019 hcl:#602927 eyr:1967 hgt:170cm ecl:grn pid:012533040 byr:1946 cid:1",
+        );
+        assert_eq!(
+            schema.validate(&p, Mode::Strict),
+            vec![FieldError {
+                key: "eyr".to_owned(),
+                reason: "1967 is not a year in 2020-2030".to_owned()
+            }]
+        );
+    }
+
+    #[test]
+    fn typed_passport() {
+        let p = passport_from(
+            "pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980 hcl:#623a2f cid:99",
+        );
+        let expected = Passport {
+            birth_year: BirthYear(1980),
+            issue_year: IssueYear(2012),
+            expiration_year: ExpirationYear(2030),
+            height: Height {
+                value: 74,
+                unit: HeightUnit::In,
+            },
+            hair_color: HairColor([0x62, 0x3a, 0x2f]),
+            eye_color: EyeColor::Green,
+            passport_id: PassportId("087499704".to_owned()),
+            country_id: Some("99".to_owned()),
+        };
+        assert_eq!(Passport::try_from(&p), Ok(expected));
+    }
+
+    #[test]
+    fn typed_passport_reports_all_errors() {
+        let p = passport_from("eyr:1972 hcl:#18171d ecl:zzz hgt:170 pid:186cm iyr:2018");
+        let failing: Vec<_> = Passport::try_from(&p)
+            .unwrap_err()
+            .into_iter()
+            .map(|e| (e.key, e.reason))
+            .collect();
+        assert_eq!(
+            failing,
+            vec![
+                ("byr".to_owned(), "missing".to_owned()),
+                (
+                    "eyr".to_owned(),
+                    "1972 is not a year in 2020-2030".to_owned()
+                ),
+                ("hgt".to_owned(), "170 has no known unit".to_owned()),
+                ("ecl".to_owned(), "zzz is not a known eye color".to_owned()),
+                ("pid".to_owned(), "186cm is not a 9 digit number".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn lenient_passport() {
+        let p = passport_from("eyr:1972 byr:1980 hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018");
+        let lenient = LenientPassport::try_from(&p).unwrap();
+        assert_eq!(lenient.birth_year, Lenient::Parsed(BirthYear(1980)));
+        assert_eq!(lenient.height, Lenient::Raw("170".to_owned()));
+        assert!(!lenient.is_strictly_valid());
+
+        let p = passport_from("eyr:1972 hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018");
+        assert_eq!(
+            LenientPassport::try_from(&p),
+            Err(vec![FieldError {
+                key: "byr".to_owned(),
+                reason: "missing".to_owned()
+            }])
+        );
+    }
+
+    #[test]
+    fn lenient_agrees_with_strict() {
+        let inputs = [
+            "pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980 hcl:#623a2f",
+            "eyr:2029 ecl:blu cid:129 byr:1989 iyr:2014 pid:896056539 hcl:#a97842 hgt:165cm",
+            "eyr:1972 cid:100 hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018 byr:1926",
+            "iyr:2019 hcl:#602927 eyr:1967 hgt:170cm ecl:grn pid:012533040 byr:1946",
+            "hgt:59cm ecl:zzz eyr:2038 hcl:74454a iyr:2023 pid:3556412378 byr:2007",
+        ];
+        for input in inputs.iter() {
+            let p = passport_from(input);
+            let lenient = LenientPassport::try_from(&p).unwrap();
+            assert_eq!(
+                lenient.is_strictly_valid(),
+                Passport::try_from(&p).is_ok(),
+                "{}",
+                input
+            );
+        }
+    }
+
+    // (key, value, valid in strict mode, valid in loose mode)
+    const FIELD_CASES: [(&str, &str, bool, bool); 39] = [
+        // Examples from the puzzle statement
+        ("byr", "2002", true, true),
+        ("byr", "2003", false, false),
+        ("hgt", "60in", true, true),
+        ("hgt", "190cm", true, true),
+        ("hgt", "190in", false, false),
+        ("hgt", "190", false, false),
+        ("hcl", "#123abc", true, true),
+        ("hcl", "#123abz", false, false),
+        ("hcl", "123abc", false, false),
+        ("ecl", "brn", true, true),
+        ("ecl", "wat", false, false),
+        ("pid", "000000001", true, true),
+        ("pid", "0123456789", false, false),
+        // Range boundaries
+        ("byr", "1920", true, true),
+        ("byr", "1919", false, false),
+        ("iyr", "2010", true, true),
+        ("iyr", "2009", false, false),
+        ("iyr", "2020", true, true),
+        ("iyr", "2021", false, false),
+        ("eyr", "2020", true, true),
+        ("eyr", "2019", false, false),
+        ("eyr", "2030", true, true),
+        ("eyr", "2031", false, false),
+        ("hgt", "150cm", true, true),
+        ("hgt", "149cm", false, false),
+        ("hgt", "193cm", true, true),
+        ("hgt", "194cm", false, false),
+        ("hgt", "59in", true, true),
+        ("hgt", "58in", false, false),
+        ("hgt", "76in", true, true),
+        ("hgt", "77in", false, false),
+        // Only accepted by the loose validators
+        ("byr", "02002", false, true),
+        ("byr", "+2002", false, true),
+        ("hgt", "+60in", false, true),
+        ("hcl", "#123ABC", false, true),
+        ("pid", "00000001a", false, true),
+        ("pid", "1234abcde", false, true),
+        ("ecl", "BRN", false, false),
+        // Non-ASCII values
+        ("hcl", "#abcdeé", false, false),
+    ];
+
+    fn typed_valid(key: &str, value: &str) -> bool {
+        match key {
+            "byr" => value.parse::<BirthYear>().is_ok(),
+            "iyr" => value.parse::<IssueYear>().is_ok(),
+            "eyr" => value.parse::<ExpirationYear>().is_ok(),
+            "hgt" => value.parse::<Height>().is_ok(),
+            "hcl" => value.parse::<HairColor>().is_ok(),
+            "ecl" => value.parse::<EyeColor>().is_ok(),
+            "pid" => value.parse::<PassportId>().is_ok(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn field_validators() {
+        let schema = Schema::parse(PASSPORT_SCHEMA).unwrap();
+        for &(key, value, strict, loose) in FIELD_CASES.iter() {
+            let mut passport = HashMap::new();
+            passport.insert(key.to_owned(), value.to_owned());
+            let field_valid = |mode| {
+                !schema
+                    .validate(&passport, mode)
+                    .iter()
+                    .any(|e| e.key == key)
+            };
+            assert_eq!(
+                field_valid(Mode::Strict),
+                strict,
+                "strict {}:{}",
+                key,
+                value
+            );
+            assert_eq!(field_valid(Mode::Loose), loose, "loose {}:{}", key, value);
+            assert_eq!(typed_valid(key, value), strict, "typed {}:{}", key, value);
+        }
+    }
 }
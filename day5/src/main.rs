@@ -1,8 +1,12 @@
 use parser::{endline, optional};
 use std::collections::BTreeSet;
+use std::env;
+use std::ops::RangeInclusive;
 
-const TOTAL_ROWS: u8 = 128;
-const TOTAL_COLUMNS: u8 = 8;
+const PLANE: BoardingPass = match BoardingPass::new(128, 8) {
+    Some(plane) => plane,
+    None => panic!("Rows and columns must be powers of two"),
+};
 
 #[derive(PartialEq, Clone, Copy, Debug)]
 enum Part {
@@ -26,36 +30,143 @@ impl Part {
             _ => None,
         }
     }
+
+    fn from_bit(bit: u32) -> Self {
+        if bit == 0 {
+            Self::Lower
+        } else {
+            Self::Upper
+        }
+    }
+
+    fn row_char(self) -> char {
+        match self {
+            Self::Lower => 'F',
+            Self::Upper => 'B',
+        }
+    }
+
+    fn col_char(self) -> char {
+        match self {
+            Self::Lower => 'L',
+            Self::Upper => 'R',
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
 struct Seat {
-    row: [Part; 7],
-    column: [Part; 3],
+    row: Vec<Part>,
+    column: Vec<Part>,
 }
 
 impl Seat {
-    fn new(row: [Part; 7], column: [Part; 3]) -> Self {
-        Seat { row, column }
+    fn new(row: impl Into<Vec<Part>>, column: impl Into<Vec<Part>>) -> Self {
+        Seat {
+            row: row.into(),
+            column: column.into(),
+        }
+    }
+
+    fn row_number(&self) -> u32 {
+        binary(&self.row)
+    }
+
+    fn column_number(&self) -> u32 {
+        binary(&self.column)
     }
 
+    // The whole pass read as a binary number, with the row in the high bits
     fn id(&self) -> u32 {
-        let row = bisect(self.row.iter(), TOTAL_ROWS - 1);
-        let column = bisect(self.column.iter(), TOTAL_COLUMNS - 1);
-        row as u32 * 8 + column as u32
+        (self.row_number() << self.column.len()) | self.column_number()
     }
 }
 
-fn bisect<'a>(parts: impl Iterator<Item = &'a Part>, start_high: u8) -> u32 {
-    let mut high = start_high;
-    let mut half = (high + 1) / 2;
-    for r in parts {
-        if *r == Part::Lower {
-            high -= half;
+// Every halving picks one bit, upper half meaning 1
+fn binary(parts: &[Part]) -> u32 {
+    parts
+        .iter()
+        .fold(0, |acc, p| (acc << 1) | (*p == Part::Upper) as u32)
+}
+
+fn bits(value: u32, count: u32) -> Vec<Part> {
+    (0..count)
+        .rev()
+        .map(|i| Part::from_bit((value >> i) & 1))
+        .collect()
+}
+
+// Encodes and decodes boarding passes for a plane with power of two numbers of rows and columns
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct BoardingPass {
+    rows: u32,
+    columns: u32,
+}
+
+impl BoardingPass {
+    const fn new(rows: u32, columns: u32) -> Option<Self> {
+        if rows.is_power_of_two()
+            && columns.is_power_of_two()
+            && rows.checked_mul(columns).is_some()
+        {
+            Some(BoardingPass { rows, columns })
+        } else {
+            None
         }
-        half /= 2;
     }
-    high as u32
+
+    fn row_bits(&self) -> u32 {
+        self.rows.trailing_zeros()
+    }
+
+    fn column_bits(&self) -> u32 {
+        self.columns.trailing_zeros()
+    }
+
+    fn seat_count(&self) -> u32 {
+        self.rows * self.columns
+    }
+
+    fn parse_seat<'a>(&self, input: &'a str) -> Option<(Seat, &'a str)> {
+        let mut rows = Vec::new();
+        let mut columns = Vec::new();
+        let mut _input = input;
+        for _ in 0..self.row_bits() {
+            let (row, rest) = parse_row(_input)?;
+            _input = rest;
+            rows.push(row);
+        }
+        for _ in 0..self.column_bits() {
+            let (column, rest) = parse_column(_input)?;
+            _input = rest;
+            columns.push(column);
+        }
+        let (_, _input) = optional(endline, _input);
+        Some((Seat::new(rows, columns), _input))
+    }
+
+    fn decode(&self, pass: &str) -> Option<Seat> {
+        match self.parse_seat(pass)? {
+            (seat, "") => Some(seat),
+            _ => None,
+        }
+    }
+
+    fn seat(&self, id: u32) -> Option<Seat> {
+        if id >= self.seat_count() {
+            return None;
+        }
+        let row = bits(id >> self.column_bits(), self.row_bits());
+        let column = bits(id & (self.columns - 1), self.column_bits());
+        Some(Seat::new(row, column))
+    }
+
+    fn encode(&self, id: u32) -> Option<String> {
+        let seat = self.seat(id)?;
+        let row = seat.row.iter().map(|p| p.row_char());
+        let column = seat.column.iter().map(|p| p.col_char());
+        Some(row.chain(column).collect())
+    }
 }
 
 fn parse_row(input: &str) -> Option<(Part, &str)> {
@@ -68,32 +179,123 @@ fn parse_column(input: &str) -> Option<(Part, &str)> {
     Some((Part::from_col_char(letter)?, &input[i + 1..]))
 }
 
-fn parse_seat(input: &str) -> Option<(Seat, &str)> {
-    let mut rows = [Part::Upper; 7];
-    let mut columns = [Part::Upper; 3];
-    let mut _input = input;
-    for row_slot in rows.iter_mut() {
-        let (row, rest) = parse_row(_input)?;
-        _input = rest;
-        *row_slot = row;
+fn parse_seats(input: &str) -> Vec<Seat> {
+    input
+        .lines()
+        .map_while(|line| PLANE.decode(line.trim_end()))
+        .collect()
+}
+
+struct SeatMap {
+    plane: BoardingPass,
+    // Number of boarding passes for every seat ID
+    passes: Vec<u32>,
+}
+
+impl SeatMap {
+    fn new(plane: BoardingPass, seats: &[Seat]) -> Self {
+        let mut passes = vec![0; plane.seat_count() as usize];
+        for seat in seats {
+            passes[seat.id() as usize] += 1;
+        }
+        SeatMap { plane, passes }
     }
-    for column_slot in columns.iter_mut() {
-        let (column, rest) = parse_column(_input)?;
-        _input = rest;
-        *column_slot = column;
+
+    fn is_taken(&self, id: u32) -> bool {
+        self.passes.get(id as usize).is_some_and(|&p| p > 0)
     }
-    let (_, _input) = optional(endline, _input);
-    Some((Seat::new(rows, columns), _input))
-}
 
-fn parse_seats(input: &str) -> Vec<Seat> {
-    let mut seats = Vec::new();
-    let mut _input = input;
-    while let Some((seat, input)) = parse_seat(_input) {
-        seats.push(seat);
-        _input = input;
+    fn row_is_empty(&self, row: u32) -> bool {
+        let first = row * self.plane.columns;
+        (first..(first + self.plane.columns)).all(|id| !self.is_taken(id))
+    }
+
+    // One line per row, front first: '#' taken, '.' free, '!' more than one pass
+    fn render(&self) -> String {
+        let mut rendered = String::new();
+        for row in self.passes.chunks(self.plane.columns as usize) {
+            for &p in row {
+                rendered.push(match p {
+                    0 => '.',
+                    1 => '#',
+                    _ => '!',
+                });
+            }
+            rendered.push('\n');
+        }
+        rendered
+    }
+
+    fn missing_runs(&self) -> Vec<RangeInclusive<u32>> {
+        let mut runs = Vec::new();
+        let mut start = None;
+        for id in 0..=self.plane.seat_count() {
+            let taken = id == self.plane.seat_count() || self.is_taken(id);
+            match (start, taken) {
+                (None, false) => start = Some(id),
+                (Some(s), true) => {
+                    runs.push(s..=(id - 1));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        runs
+    }
+
+    fn duplicates(&self) -> Vec<(u32, u32)> {
+        (0..self.plane.seat_count())
+            .map(|id| (id, self.passes[id as usize]))
+            .filter(|&(_, p)| p > 1)
+            .collect()
+    }
+
+    // Rows without a single pass at the very front of the plane, which don't exist
+    fn missing_front_rows(&self) -> Option<RangeInclusive<u32>> {
+        let count = (0..self.plane.rows)
+            .take_while(|&r| self.row_is_empty(r))
+            .count() as u32;
+        if count > 0 {
+            Some(0..=(count - 1))
+        } else {
+            None
+        }
+    }
+
+    fn missing_back_rows(&self) -> Option<RangeInclusive<u32>> {
+        let count = (0..self.plane.rows)
+            .rev()
+            .take_while(|&r| self.row_is_empty(r))
+            .count() as u32;
+        if count > 0 && count < self.plane.rows {
+            Some((self.plane.rows - count)..=(self.plane.rows - 1))
+        } else {
+            None
+        }
+    }
+
+    // Free seats with taken seats on both sides
+    fn lone_free_seats(&self) -> Vec<u32> {
+        self.missing_runs()
+            .into_iter()
+            .filter(|run| run.start() == run.end())
+            .map(|run| *run.start())
+            .filter(|&id| id > 0 && self.is_taken(id - 1) && self.is_taken(id + 1))
+            .collect()
     }
-    seats
+}
+
+fn format_runs(runs: &[RangeInclusive<u32>]) -> String {
+    runs.iter()
+        .map(|r| {
+            if r.start() == r.end() {
+                r.start().to_string()
+            } else {
+                format!("{}-{}", r.start(), r.end())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 fn main() {
@@ -101,19 +303,44 @@ fn main() {
     let seats = parse_seats(input);
     let ids = seats.iter().map(|s| s.id()).collect::<BTreeSet<u32>>();
     let highest = *ids.iter().max().unwrap();
-    println!("Part 1: highest ID {}", highest);
-    let yours = (0..=highest)
-        .filter(|i| !ids.contains(i))
-        .filter(|i| *i > 0 && ids.contains(&(i - 1)) && ids.contains(&(i + 1)))
-        .next()
-        .unwrap();
-    println!("Part 2: your seat ID is {}", yours);
+    println!(
+        "Part 1: highest ID {} ({})",
+        highest,
+        PLANE.encode(highest).unwrap()
+    );
+
+    let map = SeatMap::new(PLANE, &seats);
+    let yours = map.lone_free_seats()[0];
+    println!(
+        "Part 2: your seat ID is {} ({})",
+        yours,
+        PLANE.encode(yours).unwrap()
+    );
+
+    // Pass "map" to draw the whole plane
+    if env::args().nth(1).as_deref() == Some("map") {
+        print!("{}", map.render());
+    }
+    println!("Missing IDs: {}", format_runs(&map.missing_runs()));
+    let rows: Vec<_> = map
+        .missing_front_rows()
+        .into_iter()
+        .chain(map.missing_back_rows())
+        .collect();
+    println!("Rows that don't exist: {}", format_runs(&rows));
+    for (id, passes) in map.duplicates() {
+        println!("Seat {} has {} boarding passes", id, passes);
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    fn parse_seat(input: &str) -> Option<(Seat, &str)> {
+        PLANE.parse_seat(input)
+    }
+
     #[test]
     fn seat_id() {
         use Part::*;
@@ -176,20 +403,20 @@ mod test {
                 (
                     "FFBBFFBLRL",
                     Some((
-                        Seat {
-                            row: [Lower, Lower, Upper, Upper, Lower, Lower, Upper],
-                            column: [Lower, Upper, Lower],
-                        },
+                        Seat::new(
+                            [Lower, Lower, Upper, Upper, Lower, Lower, Upper],
+                            [Lower, Upper, Lower],
+                        ),
                         "",
                     ))
                 ),
                 (
                     "FFBBFFBLRL\nFFBBFFBLRL",
                     Some((
-                        Seat {
-                            row: [Lower, Lower, Upper, Upper, Lower, Lower, Upper],
-                            column: [Lower, Upper, Lower],
-                        },
+                        Seat::new(
+                            [Lower, Lower, Upper, Upper, Lower, Lower, Upper],
+                            [Lower, Upper, Lower],
+                        ),
                         "FFBBFFBLRL",
                     ))
                 ),
@@ -197,4 +424,98 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn boarding_pass_geometry() {
+        assert_eq!(BoardingPass::new(128, 8), Some(PLANE));
+        assert!(BoardingPass::new(1, 1).is_some());
+        assert!(BoardingPass::new(100, 8).is_none());
+        assert!(BoardingPass::new(128, 0).is_none());
+        assert!(BoardingPass::new(1 << 20, 1 << 20).is_none());
+    }
+
+    #[test]
+    fn decode_and_encode_examples() {
+        let examples = [
+            ("BFFFBBFRRR", 70, 7, 567),
+            ("FFFBBBFRRR", 14, 7, 119),
+            ("BBFFBBFRLL", 102, 4, 820),
+        ];
+        for &(pass, row, column, id) in examples.iter() {
+            let seat = PLANE.decode(pass).unwrap();
+            assert_eq!(seat.row_number(), row);
+            assert_eq!(seat.column_number(), column);
+            assert_eq!(seat.id(), id);
+            assert_eq!(PLANE.encode(id).unwrap(), pass);
+        }
+        assert!(PLANE.decode("BFFFBBFRR").is_none());
+        assert!(PLANE.decode("BFFFBBFRRRL").is_none());
+        assert!(PLANE.encode(1024).is_none());
+    }
+
+    #[test]
+    fn round_trip() {
+        let planes = [(1, 1), (1, 4), (2, 1), (16, 2), (128, 8), (1024, 32)];
+        for &(rows, columns) in planes.iter() {
+            let plane = BoardingPass::new(rows, columns).unwrap();
+            for id in 0..plane.seat_count() {
+                let pass = plane.encode(id).unwrap();
+                assert_eq!(pass.len() as u32, plane.row_bits() + plane.column_bits());
+                let seat = plane.decode(&pass).unwrap();
+                assert_eq!(seat.id(), id);
+                assert_eq!(seat, plane.seat(id).unwrap());
+                assert_eq!(seat.row_number(), id / columns);
+                assert_eq!(seat.column_number(), id % columns);
+            }
+            assert!(plane.encode(plane.seat_count()).is_none());
+        }
+    }
+
+    #[test]
+    fn test_parse_seats() {
+        let seats = parse_seats("BFFFBBFRRR\r\nFFFBBBFRRR\nBBFFBBFRL\nBBFFBBFRLL");
+        let ids: Vec<u32> = seats.iter().map(|s| s.id()).collect();
+        assert_eq!(ids, vec![567, 119]);
+    }
+
+    fn small_map(passes: &str) -> SeatMap {
+        let plane = BoardingPass::new(4, 2).unwrap();
+        let seats: Vec<Seat> = passes
+            .split_whitespace()
+            .map(|p| plane.decode(p).unwrap())
+            .collect();
+        SeatMap::new(plane, &seats)
+    }
+
+    #[test]
+    fn seat_map_report() {
+        // Row 0 doesn't exist, seat 4 is free and seats 3 and 6 have two passes each
+        let map = small_map("FBL FBR FBR BFR BBL BBL");
+        assert_eq!(map.render(), "..\n#!\n.#\n!.\n");
+        assert_eq!(map.missing_runs(), vec![0..=1, 4..=4, 7..=7]);
+        assert_eq!(map.duplicates(), vec![(3, 2), (6, 2)]);
+        assert_eq!(map.missing_front_rows(), Some(0..=0));
+        assert_eq!(map.missing_back_rows(), None);
+        assert_eq!(map.lone_free_seats(), vec![4]);
+    }
+
+    #[test]
+    fn seat_map_empty_rows() {
+        let map = small_map("FBL BFL BFR");
+        assert_eq!(map.missing_front_rows(), Some(0..=0));
+        assert_eq!(map.missing_back_rows(), Some(3..=3));
+        assert_eq!(map.missing_runs(), vec![0..=1, 3..=3, 6..=7]);
+        assert_eq!(map.lone_free_seats(), vec![3]);
+
+        let map = small_map("");
+        assert_eq!(map.missing_front_rows(), Some(0..=3));
+        assert_eq!(map.missing_back_rows(), None);
+        assert_eq!(map.missing_runs(), vec![0..=7]);
+    }
+
+    #[test]
+    fn format_missing_runs() {
+        assert_eq!(format_runs(&[0..=1, 4..=4]), "0-1, 4");
+        assert_eq!(format_runs(&[]), "");
+    }
 }
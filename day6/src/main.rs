@@ -1,50 +1,250 @@
-use std::collections::{HashMap, HashSet};
+use std::env;
 
-use parser::{endline, fixed, many1, optional, take_while1};
-
-fn blank_line(input: &str) -> Option<(&str, &str)> {
-    fixed("\n\n", input)
-}
+use parser::{many1, record, take_while1};
 
 fn parse_answers(input: &str) -> Option<(&str, &str)> {
-    let (_, input) = optional(endline, input);
     take_while1(|c| c.is_ascii_lowercase(), input)
 }
 
 fn parse_group(input: &str) -> Option<(Vec<&str>, &str)> {
-    let (group, input) = many1(parse_answers, input)?;
-    let (_, input) = optional(blank_line, input);
-    Some((group, input))
+    record(parse_answers, input)
 }
 
-fn sum_everyone_answered(group: &[&str]) -> usize {
-    let mut counts_map = HashMap::new();
-    for c in group.iter().flat_map(|a| a.chars()) {
-        let char_count = counts_map.entry(c).or_insert(0);
-        *char_count += 1;
+const QUESTIONS: u32 = 26;
+
+// Questions a to z as bits 0 to 25
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+struct AnswerSet(u32);
+
+impl AnswerSet {
+    const EMPTY: AnswerSet = AnswerSet(0);
+    const ALL: AnswerSet = AnswerSet((1 << QUESTIONS) - 1);
+
+    fn from_answers(answers: &str) -> Self {
+        answers
+            .chars()
+            .filter(|c| c.is_ascii_lowercase())
+            .fold(AnswerSet::EMPTY, |set, c| {
+                AnswerSet(set.0 | 1 << (c as u32 - 'a' as u32))
+            })
+    }
+
+    fn union(self, other: AnswerSet) -> AnswerSet {
+        AnswerSet(self.0 | other.0)
+    }
+
+    fn intersection(self, other: AnswerSet) -> AnswerSet {
+        AnswerSet(self.0 & other.0)
+    }
+
+    fn difference(self, other: AnswerSet) -> AnswerSet {
+        AnswerSet(self.0 & !other.0)
     }
-    counts_map.values().filter(|&v| *v == group.len()).count()
+
+    fn symmetric_difference(self, other: AnswerSet) -> AnswerSet {
+        AnswerSet(self.0 ^ other.0)
+    }
+
+    fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+}
+
+fn answer_sets(group: &[&str]) -> Vec<AnswerSet> {
+    group.iter().map(|a| AnswerSet::from_answers(a)).collect()
+}
+
+fn anyone_answered(group: &[AnswerSet]) -> AnswerSet {
+    group.iter().fold(AnswerSet::EMPTY, |acc, &s| acc.union(s))
+}
+
+// An empty group answered nothing, rather than everything
+fn everyone_answered(group: &[AnswerSet]) -> AnswerSet {
+    group
+        .iter()
+        .copied()
+        .reduce(|acc, s| acc.intersection(s))
+        .unwrap_or(AnswerSet::EMPTY)
+}
+
+// Entry k holds the questions answered by at least k members
+fn answered_by_at_least(group: &[AnswerSet]) -> Vec<AnswerSet> {
+    let mut at_least = vec![AnswerSet::EMPTY; group.len() + 2];
+    at_least[0] = AnswerSet::ALL;
+    for &member in group {
+        for k in (1..=group.len()).rev() {
+            at_least[k] = at_least[k].union(at_least[k - 1].intersection(member));
+        }
+    }
+    at_least
+}
+
+fn answered_by_exactly(group: &[AnswerSet], k: usize) -> AnswerSet {
+    if k > group.len() {
+        return AnswerSet::EMPTY;
+    }
+    let at_least = answered_by_at_least(group);
+    at_least[k].difference(at_least[k + 1])
+}
+
+fn answered_by_majority(group: &[AnswerSet]) -> AnswerSet {
+    answered_by_at_least(group)[group.len() / 2 + 1]
+}
+
+// Questions some members answered but not all
+fn disagreements(group: &[AnswerSet]) -> AnswerSet {
+    anyone_answered(group).symmetric_difference(everyone_answered(group))
+}
+
+fn question(index: usize) -> char {
+    (b'a' + index as u8) as char
+}
+
+// How many members answered each question
+fn question_counts(group: &[&str]) -> [usize; QUESTIONS as usize] {
+    let mut counts = [0; QUESTIONS as usize];
+    for set in answer_sets(group) {
+        for (i, count) in counts.iter_mut().enumerate() {
+            if set.0 & (1 << i) != 0 {
+                *count += 1;
+            }
+        }
+    }
+    counts
+}
+
+#[derive(Debug, PartialEq)]
+struct GroupStats {
+    members: usize,
+    union: usize,
+    intersection: usize,
+    // Ties go to the earliest question, unanswered questions are never least common
+    most_common: Option<char>,
+    least_common: Option<char>,
+}
+
+impl GroupStats {
+    fn from_group(group: &[&str]) -> Self {
+        let sets = answer_sets(group);
+        let counts = question_counts(group);
+        let answered = counts.iter().enumerate().filter(|(_, &c)| c > 0);
+        let most_common = answered
+            .clone()
+            .min_by_key(|(i, &c)| (std::cmp::Reverse(c), *i))
+            .map(|(i, _)| question(i));
+        let least_common = answered
+            .min_by_key(|(i, &c)| (c, *i))
+            .map(|(i, _)| question(i));
+        GroupStats {
+            members: group.len(),
+            union: anyone_answered(&sets).len(),
+            intersection: everyone_answered(&sets).len(),
+            most_common,
+            least_common,
+        }
+    }
+}
+
+// Members answering each question, summed over all groups
+fn histogram(groups: &[Vec<&str>]) -> [usize; QUESTIONS as usize] {
+    let mut totals = [0; QUESTIONS as usize];
+    for group in groups {
+        for (total, count) in totals.iter_mut().zip(question_counts(group).iter()) {
+            *total += count;
+        }
+    }
+    totals
+}
+
+fn stats_csv(stats: &[GroupStats]) -> String {
+    let mut csv = String::from("group,members,union,intersection,most_common,least_common\n");
+    for (i, s) in stats.iter().enumerate() {
+        csv += &format!(
+            "{},{},{},{},{},{}\n",
+            i + 1,
+            s.members,
+            s.union,
+            s.intersection,
+            s.most_common.map(String::from).unwrap_or_default(),
+            s.least_common.map(String::from).unwrap_or_default()
+        );
+    }
+    csv
+}
+
+fn stats_json(stats: &[GroupStats]) -> String {
+    let json_char = |c: Option<char>| c.map_or("null".to_string(), |c| format!("\"{}\"", c));
+    let objects: Vec<String> = stats
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            format!(
+                "{{\"group\":{},\"members\":{},\"union\":{},\"intersection\":{},\"most_common\":{},\"least_common\":{}}}",
+                i + 1,
+                s.members,
+                s.union,
+                s.intersection,
+                json_char(s.most_common),
+                json_char(s.least_common)
+            )
+        })
+        .collect();
+    format!("[{}]\n", objects.join(","))
+}
+
+fn histogram_csv(histogram: &[usize]) -> String {
+    let mut csv = String::from("question,answers\n");
+    for (i, count) in histogram.iter().enumerate() {
+        csv += &format!("{},{}\n", question(i), count);
+    }
+    csv
+}
+
+fn histogram_json(histogram: &[usize]) -> String {
+    let entries: Vec<String> = histogram
+        .iter()
+        .enumerate()
+        .map(|(i, count)| format!("\"{}\":{}", question(i), count))
+        .collect();
+    format!("{{{}}}\n", entries.join(","))
 }
 
 fn main() {
     let input = include_str!("input");
     let (groups, input) = many1(parse_group, input).unwrap();
     assert_eq!(input, "");
-    let sum_answers_1: usize = groups
-        .iter()
-        .map(|g| {
-            g.iter()
-                .flat_map(|a| a.chars())
-                .fold(HashSet::new(), |mut s, a| {
-                    s.insert(a);
-                    s
-                })
-        })
-        .map(|s| s.len())
-        .sum();
+    let sets: Vec<Vec<AnswerSet>> = groups.iter().map(|g| answer_sets(g)).collect();
+    let sum_answers_1: usize = sets.iter().map(|g| anyone_answered(g).len()).sum();
     println!("Part 1: Sum of answers: {}", sum_answers_1);
-    let sum_answers_2: usize = groups.iter().map(|g| sum_everyone_answered(g)).sum();
+    let sum_answers_2: usize = sets.iter().map(|g| everyone_answered(g).len()).sum();
     println!("Part 2: Sum of answers: {}", sum_answers_2);
+    let sum_majority: usize = sets.iter().map(|g| answered_by_majority(g).len()).sum();
+    let sum_disagreements: usize = sets.iter().map(|g| disagreements(g).len()).sum();
+    let sum_single: usize = sets
+        .iter()
+        .filter(|g| g.len() > 1)
+        .map(|g| answered_by_exactly(g, 1).len())
+        .sum();
+    println!(
+        "Answered by a majority: {}, by a single member of a larger group: {}, by only some members: {}",
+        sum_majority, sum_single, sum_disagreements
+    );
+
+    // Pass "csv" or "json" to export per group stats, followed by "histogram" for question totals
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(format) = args.first() {
+        let stats: Vec<GroupStats> = groups.iter().map(|g| GroupStats::from_group(g)).collect();
+        let totals = histogram(&groups);
+        let with_histogram = args.get(1).map(String::as_str) == Some("histogram");
+        let export = match (format.as_str(), with_histogram) {
+            ("csv", false) => stats_csv(&stats),
+            ("csv", true) => histogram_csv(&totals),
+            ("json", false) => stats_json(&stats),
+            ("json", true) => histogram_json(&totals),
+            _ => panic!("Unknown export format {}", format),
+        };
+        print!("{}", export);
+    }
 }
 
 #[cfg(test)]
@@ -57,7 +257,7 @@ mod test {
         assert_eq!(res, Some(("ab", "\nc")));
 
         let res = parse_answers("\nc");
-        assert_eq!(res, Some(("c", "")));
+        assert!(res.is_none());
 
         let res = parse_answers("");
         assert!(res.is_none());
@@ -71,7 +271,111 @@ mod test {
         let res = parse_group("c");
         assert_eq!(res, Some((vec!["c"], "")));
 
+        let res = parse_group("ab\r\na \r\n\r\nc\r\n");
+        assert_eq!(res, Some((vec!["ab", "a"], "c\r\n")));
+
         let res = parse_group("");
         assert!(res.is_none());
     }
+
+    #[test]
+    fn answer_set_algebra() {
+        let abc = AnswerSet::from_answers("abc");
+        let bcd = AnswerSet::from_answers("dcb");
+        assert_eq!(abc.len(), 3);
+        assert_eq!(abc.union(bcd), AnswerSet::from_answers("abcd"));
+        assert_eq!(abc.intersection(bcd), AnswerSet::from_answers("bc"));
+        assert_eq!(abc.difference(bcd), AnswerSet::from_answers("a"));
+        assert_eq!(abc.symmetric_difference(bcd), AnswerSet::from_answers("ad"));
+        assert_eq!(AnswerSet::ALL.len(), 26);
+        assert_eq!(AnswerSet::from_answers("aa"), AnswerSet::from_answers("a"));
+    }
+
+    #[test]
+    fn group_queries() {
+        let group = answer_sets(&["abc", "ab", "ax", "b"]);
+        assert_eq!(anyone_answered(&group), AnswerSet::from_answers("abcx"));
+        assert_eq!(everyone_answered(&group), AnswerSet::EMPTY);
+        assert_eq!(
+            answered_by_exactly(&group, 1),
+            AnswerSet::from_answers("cx")
+        );
+        assert_eq!(
+            answered_by_exactly(&group, 3),
+            AnswerSet::from_answers("ab")
+        );
+        assert_eq!(
+            answered_by_exactly(&group, 0),
+            AnswerSet::ALL.difference(anyone_answered(&group))
+        );
+        assert_eq!(answered_by_exactly(&group, 5), AnswerSet::EMPTY);
+        assert_eq!(answered_by_majority(&group), AnswerSet::from_answers("ab"));
+        assert_eq!(disagreements(&group), AnswerSet::from_answers("abcx"));
+        assert_eq!(
+            answered_by_majority(&answer_sets(&["ab", "b"])),
+            AnswerSet::from_answers("b")
+        );
+        assert_eq!(everyone_answered(&[]), AnswerSet::EMPTY);
+    }
+
+    #[test]
+    fn example_sums() {
+        let input = "abc\n\na\nb\nc\n\nab\nac\n\na\na\na\na\n\nb";
+        let (groups, _) = many1(parse_group, input).unwrap();
+        let sets: Vec<Vec<AnswerSet>> = groups.iter().map(|g| answer_sets(g)).collect();
+        let anyone: Vec<usize> = sets.iter().map(|g| anyone_answered(g).len()).collect();
+        assert_eq!(anyone, vec![3, 3, 3, 1, 1]);
+        let everyone: Vec<usize> = sets.iter().map(|g| everyone_answered(g).len()).collect();
+        assert_eq!(everyone, vec![3, 0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn group_stats() {
+        let stats = GroupStats::from_group(&["abc", "ab", "ax", "b"]);
+        assert_eq!(
+            stats,
+            GroupStats {
+                members: 4,
+                union: 4,
+                intersection: 0,
+                most_common: Some('a'),
+                least_common: Some('c'),
+            }
+        );
+        let stats = GroupStats::from_group(&["zy", "y"]);
+        assert_eq!(stats.most_common, Some('y'));
+        assert_eq!(stats.least_common, Some('z'));
+        let stats = GroupStats::from_group(&[]);
+        assert_eq!(stats.most_common, None);
+        assert_eq!(stats.least_common, None);
+    }
+
+    #[test]
+    fn histogram_totals() {
+        let totals = histogram(&[vec!["ab", "a"], vec!["c"]]);
+        assert_eq!(&totals[..4], &[2, 1, 1, 0]);
+        assert_eq!(totals.iter().sum::<usize>(), 4);
+        let csv = histogram_csv(&totals);
+        assert!(csv.starts_with("question,answers\na,2\nb,1\n"));
+        assert_eq!(csv.lines().count(), 27);
+        let json = histogram_json(&totals);
+        assert!(json.starts_with("{\"a\":2,\"b\":1,\"c\":1,\"d\":0,"));
+    }
+
+    #[test]
+    fn export_stats() {
+        let stats = vec![
+            GroupStats::from_group(&["ab", "a"]),
+            GroupStats::from_group(&[]),
+        ];
+        assert_eq!(
+            stats_csv(&stats),
+            "group,members,union,intersection,most_common,least_common\n1,2,2,1,a,b\n2,0,0,0,,\n"
+        );
+        assert_eq!(
+            stats_json(&stats),
+            "[{\"group\":1,\"members\":2,\"union\":2,\"intersection\":1,\"most_common\":\"a\",\"least_common\":\"b\"},\
+{\"group\":2,\"members\":0,\"union\":0,\"intersection\":0,\"most_common\":null,\"least_common\":null}]\n"
+        );
+    }
 }
@@ -4,7 +4,9 @@ use parser::{endline, fixed, many1, optional, unsigned_number, words};
 
 type BagsMap<'a> = HashMap<&'a str, Vec<(u64, &'a str)>>;
 
-fn parse_bag_line(input: &str) -> Option<((&str, Vec<(u64, &str)>), &str)> {
+type BagLine<'a> = (&'a str, Vec<(u64, &'a str)>);
+
+fn parse_bag_line(input: &str) -> Option<(BagLine<'_>, &str)> {
     let (color, input) = words(2, input)?;
     let (_, input) = fixed(" bags contain ", input)?;
     let (contains, input) = parse_contained_bags(input)?;
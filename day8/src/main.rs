@@ -20,7 +20,7 @@ impl BootCode {
         BootCode {
             accumulator: 0,
             ip: 0,
-            instructions: instructions,
+            instructions,
             visited: vec![false; instructions_len],
         }
     }
@@ -1,35 +1,182 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
 use parser::{endline_terminated, many1, unsigned_number};
 
-fn number_is_valid(target_num: u64, preamble: &[u64]) -> bool {
-    preamble.iter().enumerate().any(|(i, &num1)| {
-        preamble
+const DEFAULT_PREAMBLE_SIZE: usize = 25;
+
+// Index of the number that pushed a sum out of range
+#[derive(Debug, PartialEq)]
+struct Overflow {
+    index: usize,
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "arithmetic overflow at number {}", self.index)
+    }
+}
+
+impl Error for Overflow {}
+
+// Keeps the sums of every pair in the window, updating them as numbers enter and leave
+struct Validator {
+    preamble_size: usize,
+    window: VecDeque<u64>,
+    pair_sums: HashMap<u64, usize>,
+    position: usize,
+}
+
+impl Validator {
+    fn new(preamble_size: usize) -> Self {
+        Validator {
+            preamble_size,
+            window: VecDeque::with_capacity(preamble_size),
+            pair_sums: HashMap::new(),
+            position: 0,
+        }
+    }
+
+    // Numbers in the preamble are always valid. The window is left untouched on overflow
+    fn push(&mut self, num: u64) -> Result<bool, Overflow> {
+        let index = self.position;
+        let skip_oldest = (self.window.len() == self.preamble_size) as usize;
+        let new_sums = self
+            .window
             .iter()
-            .skip(i + 1)
-            .any(|&num2| num1 + num2 == target_num)
-    })
+            .skip(skip_oldest)
+            .map(|&other| num.checked_add(other).ok_or(Overflow { index }))
+            .collect::<Result<Vec<u64>, Overflow>>()?;
+        self.position += 1;
+
+        let valid = self.window.len() < self.preamble_size || self.pair_sums.contains_key(&num);
+        if self.window.len() == self.preamble_size {
+            if let Some(oldest) = self.window.pop_front() {
+                for &other in self.window.iter() {
+                    let sum = oldest + other;
+                    let count = self.pair_sums.get_mut(&sum).unwrap();
+                    *count -= 1;
+                    if *count == 0 {
+                        self.pair_sums.remove(&sum);
+                    }
+                }
+            }
+        }
+        if self.preamble_size > 0 {
+            for sum in new_sums {
+                *self.pair_sums.entry(sum).or_insert(0) += 1;
+            }
+            self.window.push_back(num);
+        }
+        Ok(valid)
+    }
 }
 
-fn first_invalid_number(numbers: &[u64], preamble_size: usize) -> Option<u64> {
+// Every number that is not a sum of two of the previous preamble_size numbers, with its index.
+// Stops after the first overflow
+fn invalid_numbers(
+    numbers: impl IntoIterator<Item = u64>,
+    preamble_size: usize,
+) -> impl Iterator<Item = Result<(usize, u64), Overflow>> {
+    let mut validator = Validator::new(preamble_size);
     numbers
-        .windows(preamble_size)
-        .zip(numbers.iter().skip(preamble_size))
-        .find(|(window, &num)| !number_is_valid(num, window))
-        .map(|(_, &num)| num)
+        .into_iter()
+        .enumerate()
+        .scan(false, move |failed, (i, num)| {
+            if *failed {
+                return None;
+            }
+            match validator.push(num) {
+                Ok(true) => Some(None),
+                Ok(false) => Some(Some(Ok((i, num)))),
+                Err(e) => {
+                    *failed = true;
+                    Some(Some(Err(e)))
+                }
+            }
+        })
+        .flatten()
 }
 
-fn contiguous_sum(target_num: u64, numbers: &[u64]) -> Option<&[u64]> {
-    for (i, &num1) in numbers.iter().enumerate() {
-        let mut sum = num1;
-        for (j, &num2) in numbers.iter().skip(i + 1).enumerate() {
-            sum += num2;
-            if sum == target_num {
-                return Some(&numbers[i..=(i + j + 1)]);
-            } else if sum > target_num {
-                break;
+fn first_invalid_number(numbers: &[u64], preamble_size: usize) -> Result<Option<u64>, Overflow> {
+    let first = invalid_numbers(numbers.iter().copied(), preamble_size)
+        .next()
+        .transpose()?;
+    Ok(first.map(|(_, num)| num))
+}
+
+// Finds the earliest starting range of at least two numbers summing to the target, shortest first.
+// Only valid for non-negative numbers, where the end of the range never moves back as the start advances
+fn contiguous_sum_window(
+    target_num: u64,
+    numbers: &[u64],
+) -> Result<Option<Range<usize>>, Overflow> {
+    let (mut end, mut sum) = (0, 0u64);
+    for start in 0..numbers.len() {
+        while end < numbers.len() && (end < start + 2 || sum < target_num) {
+            sum = sum
+                .checked_add(numbers[end])
+                .ok_or(Overflow { index: end })?;
+            end += 1;
+        }
+        if end >= start + 2 && sum == target_num {
+            return Ok(Some(start..end));
+        }
+        sum -= numbers[start];
+    }
+    Ok(None)
+}
+
+// Same range as contiguous_sum_window, but works with zero and negative numbers
+fn contiguous_sum_prefix(
+    target_num: i64,
+    numbers: &[i64],
+) -> Result<Option<Range<usize>>, Overflow> {
+    let mut prefix_sums = Vec::with_capacity(numbers.len() + 1);
+    prefix_sums.push(0i64);
+    for (index, &num) in numbers.iter().enumerate() {
+        let sum = prefix_sums.last().unwrap().checked_add(num);
+        prefix_sums.push(sum.ok_or(Overflow { index })?);
+    }
+
+    // First index of each prefix sum, only holding starts at least two before the current end
+    let mut first_seen: HashMap<i64, usize> = HashMap::new();
+    let mut best: Option<Range<usize>> = None;
+    for end in 2..prefix_sums.len() {
+        first_seen.entry(prefix_sums[end - 2]).or_insert(end - 2);
+        let wanted = prefix_sums[end]
+            .checked_sub(target_num)
+            .ok_or(Overflow { index: end - 1 })?;
+        if let Some(&start) = first_seen.get(&wanted) {
+            if best.as_ref().is_none_or(|b| start < b.start) {
+                best = Some(start..end);
             }
         }
     }
-    None
+    Ok(best)
+}
+
+fn to_signed(numbers: &[u64]) -> Result<Vec<i64>, Overflow> {
+    numbers
+        .iter()
+        .enumerate()
+        .map(|(index, &n)| i64::try_from(n).map_err(|_| Overflow { index }))
+        .collect()
+}
+
+// Sum of the smallest and largest number in the range
+fn encryption_weakness(numbers: &[u64], range: Range<usize>) -> Result<u64, Overflow> {
+    let start = range.start;
+    let window = &numbers[range];
+    let smallest = *window.iter().min().unwrap();
+    let (largest_index, &largest) = window.iter().enumerate().max_by_key(|&(_, n)| n).unwrap();
+    smallest.checked_add(largest).ok_or(Overflow {
+        index: start + largest_index,
+    })
 }
 
 fn main() {
@@ -38,29 +185,67 @@ fn main() {
         .expect("Failed to parse input");
     assert!(rest.is_empty());
 
-    const PREAMBLE_SIZE: usize = 25;
-    let first_invalid = first_invalid_number(&numbers, PREAMBLE_SIZE)
+    // Pass a number to change the preamble size, and "signed" to search for the contiguous sum
+    // with prefix sums, which also handles zero and negative numbers
+    let mut preamble_size = DEFAULT_PREAMBLE_SIZE;
+    let mut signed = false;
+    for arg in env::args().skip(1) {
+        if arg == "signed" {
+            signed = true;
+        } else {
+            preamble_size = arg.parse().expect("Preamble size must be a number");
+        }
+    }
+    let first_invalid = first_invalid_number(&numbers, preamble_size)
+        .expect("Failed to validate numbers")
         .expect("Could not find the first invalid number");
     println!("Part 1: First invalid number: {}", first_invalid);
-    let sum_window =
-        contiguous_sum(first_invalid, &numbers).expect("Could not find a contiguous sum");
-    println!(
-        "Part 2: Sum of smallest and largest: {}",
-        sum_window.iter().min().unwrap() + sum_window.iter().max().unwrap()
-    );
+    let invalid = invalid_numbers(numbers.iter().copied(), preamble_size)
+        .collect::<Result<Vec<_>, Overflow>>()
+        .expect("Failed to validate numbers");
+    println!("{} invalid numbers in total", invalid.len());
+    let range = if signed {
+        let signed_numbers = to_signed(&numbers).expect("Numbers too large for signed search");
+        let (target_index, _) = invalid[0];
+        contiguous_sum_prefix(signed_numbers[target_index], &signed_numbers)
+    } else {
+        contiguous_sum_window(first_invalid, &numbers)
+    };
+    let range = range
+        .expect("Failed to sum numbers")
+        .expect("Could not find a contiguous sum");
+    let weakness = encryption_weakness(&numbers, range).expect("Failed to sum numbers");
+    println!("Part 2: Sum of smallest and largest: {}", weakness);
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    // The original pair scan, kept as a reference for the streaming validator
+    fn number_is_valid(target_num: u64, preamble: &[u64]) -> bool {
+        preamble.iter().enumerate().any(|(i, &num1)| {
+            preamble
+                .iter()
+                .skip(i + 1)
+                .any(|&num2| num1 + num2 == target_num)
+        })
+    }
+
     #[test]
     fn test_number_is_valid() {
         let preamble = [35, 50, 15, 25, 47];
-        assert!(number_is_valid(40, &preamble));
-        assert!(number_is_valid(50, &preamble));
-        assert!(!number_is_valid(100, &preamble));
-        assert!(!number_is_valid(1, &preamble));
+        let is_valid = |num| {
+            let mut validator = Validator::new(preamble.len());
+            for &n in preamble.iter() {
+                assert_eq!(validator.push(n), Ok(true));
+            }
+            validator.push(num).unwrap()
+        };
+        assert!(is_valid(40));
+        assert!(is_valid(50));
+        assert!(!is_valid(100));
+        assert!(!is_valid(1));
     }
 
     #[test]
@@ -69,7 +254,84 @@ mod test {
             35, 20, 15, 25, 47, 40, 62, 55, 65, 95, 102, 117, 150, 182, 127, 219, 299, 277, 309,
             576,
         ];
-        assert_eq!(first_invalid_number(&numbers, 5), Some(127));
+        assert_eq!(first_invalid_number(&numbers, 5), Ok(Some(127)));
+        let all: Vec<_> = invalid_numbers(numbers.iter().copied(), 5)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(all, vec![(14, 127)]);
+        let all: Vec<_> = invalid_numbers(numbers.iter().copied(), 3)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(all[0], (3, 25));
+    }
+
+    #[test]
+    fn validator_edge_cases() {
+        let all: Vec<_> = invalid_numbers(vec![1, 2, 3, 4], 0)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(all, vec![(0, 1), (1, 2), (2, 3), (3, 4)]);
+        let all: Vec<_> = invalid_numbers(vec![5, 5, 10, 10, 15, 25], 2)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(all, vec![(3, 10), (4, 15)]);
+        let all: Vec<_> = invalid_numbers(vec![1, 2], 5)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(all.is_empty());
+    }
+
+    #[test]
+    fn overflow_errors() {
+        let max = u64::MAX;
+        let found: Vec<_> = invalid_numbers(vec![1, max, 3, 4], 2).collect();
+        assert_eq!(found, vec![Err(Overflow { index: 1 })]);
+        assert_eq!(first_invalid_number(&[max, 1], 1), Ok(Some(1)));
+        assert_eq!(
+            first_invalid_number(&[max, 1, max], 5),
+            Err(Overflow { index: 1 })
+        );
+        assert_eq!(first_invalid_number(&[max, 0, max], 2), Ok(None));
+        assert_eq!(contiguous_sum_window(max, &[max - 1, 1]), Ok(Some(0..2)));
+        assert_eq!(
+            contiguous_sum_window(max, &[2, max]),
+            Err(Overflow { index: 1 })
+        );
+        assert_eq!(
+            contiguous_sum_prefix(0, &[i64::MAX, 1]),
+            Err(Overflow { index: 1 })
+        );
+        assert_eq!(
+            contiguous_sum_prefix(i64::MIN, &[1, 0]),
+            Err(Overflow { index: 1 })
+        );
+        assert_eq!(encryption_weakness(&[1, 2, 7, 4], 1..4), Ok(9));
+        assert_eq!(to_signed(&[0, 5]), Ok(vec![0, 5]));
+        assert_eq!(to_signed(&[0, max]), Err(Overflow { index: 1 }));
+        assert_eq!(
+            encryption_weakness(&[3, max - 1, 2, max, 1], 1..4),
+            Err(Overflow { index: 3 })
+        );
+        assert_eq!(
+            Overflow { index: 3 }.to_string(),
+            "arithmetic overflow at number 3"
+        );
+    }
+
+    // The original nested loop search, kept as a reference for the faster versions
+    fn contiguous_sum(target_num: u64, numbers: &[u64]) -> Option<&[u64]> {
+        for (i, &num1) in numbers.iter().enumerate() {
+            let mut sum = num1;
+            for (j, &num2) in numbers.iter().skip(i + 1).enumerate() {
+                sum += num2;
+                if sum == target_num {
+                    return Some(&numbers[i..=(i + j + 1)]);
+                } else if sum > target_num {
+                    break;
+                }
+            }
+        }
+        None
     }
 
     #[test]
@@ -83,5 +345,115 @@ mod test {
             contiguous_sum(target_num, &numbers),
             Some(&[15, 25, 47, 40][..])
         );
+        assert_eq!(contiguous_sum_window(target_num, &numbers), Ok(Some(2..6)));
+        let signed: Vec<i64> = numbers.iter().map(|&n| n as i64).collect();
+        assert_eq!(
+            contiguous_sum_prefix(target_num as i64, &signed),
+            Ok(Some(2..6))
+        );
+    }
+
+    #[test]
+    fn contiguous_sum_edge_cases() {
+        assert_eq!(contiguous_sum_window(5, &[5]), Ok(None));
+        assert_eq!(contiguous_sum_window(5, &[5, 0]), Ok(Some(0..2)));
+        assert_eq!(contiguous_sum_window(0, &[0, 0]), Ok(Some(0..2)));
+        assert_eq!(contiguous_sum_window(1, &[]), Ok(None));
+        assert_eq!(contiguous_sum_prefix(5, &[5]), Ok(None));
+        assert_eq!(contiguous_sum_prefix(0, &[]), Ok(None));
+        assert_eq!(contiguous_sum_prefix(-3, &[4, -1, -2, 7]), Ok(Some(1..3)));
+        assert_eq!(contiguous_sum_prefix(4, &[4, 3, -3, 9]), Ok(Some(0..3)));
+        assert_eq!(contiguous_sum_prefix(0, &[1, -1, 1]), Ok(Some(0..2)));
+    }
+
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self, below: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % below
+        }
+    }
+
+    fn random_numbers(rng: &mut XorShift, max: u64) -> Vec<u64> {
+        let len = rng.next(20);
+        (0..len).map(|_| rng.next(max)).collect()
+    }
+
+    fn to_range(numbers: &[u64], found: Option<&[u64]>) -> Option<Range<usize>> {
+        found.map(|slice| {
+            let start =
+                (slice.as_ptr() as usize - numbers.as_ptr() as usize) / std::mem::size_of::<u64>();
+            start..start + slice.len()
+        })
+    }
+
+    #[test]
+    fn random_equivalence() {
+        let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..5000 {
+            let numbers = random_numbers(&mut rng, 10);
+            let target_num = rng.next(40);
+            let expected = to_range(&numbers, contiguous_sum(target_num, &numbers));
+            assert_eq!(
+                contiguous_sum_window(target_num, &numbers),
+                Ok(expected.clone()),
+                "{:?} {}",
+                numbers,
+                target_num
+            );
+            let signed: Vec<i64> = numbers.iter().map(|&n| n as i64).collect();
+            assert_eq!(
+                contiguous_sum_prefix(target_num as i64, &signed),
+                Ok(expected.clone()),
+                "{:?} {}",
+                numbers,
+                target_num
+            );
+        }
+    }
+
+    #[test]
+    fn validator_matches_pair_scan() {
+        let mut rng = XorShift(0x1234_5678_9abc_def1);
+        for _ in 0..2000 {
+            let numbers = random_numbers(&mut rng, 12);
+            let preamble_size = rng.next(5) as usize;
+            let expected: Vec<(usize, u64)> = numbers
+                .iter()
+                .enumerate()
+                .skip(preamble_size)
+                .filter(|&(i, &num)| !number_is_valid(num, &numbers[i - preamble_size..i]))
+                .map(|(i, &num)| (i, num))
+                .collect();
+            let found: Vec<_> = invalid_numbers(numbers.iter().copied(), preamble_size)
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(found, expected, "{:?} {}", numbers, preamble_size);
+        }
+    }
+
+    #[test]
+    fn random_signed_matches_brute_force() {
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        for _ in 0..5000 {
+            let numbers: Vec<i64> = random_numbers(&mut rng, 11)
+                .iter()
+                .map(|&n| n as i64 - 5)
+                .collect();
+            let target_num = rng.next(21) as i64 - 10;
+            let expected = (0..numbers.len())
+                .flat_map(|start| (start + 2..=numbers.len()).map(move |end| start..end))
+                .find(|r| numbers[r.clone()].iter().sum::<i64>() == target_num);
+            assert_eq!(
+                contiguous_sum_prefix(target_num, &numbers),
+                Ok(expected),
+                "{:?} {}",
+                numbers,
+                target_num
+            );
+        }
     }
 }
@@ -1,12 +1,15 @@
+use std::error::Error;
+use std::fmt;
+
 // Stolen from Nom, more or less
 // TODO: Figure out how to make this with &str instead of generic I...
 pub trait Parser<O, I> {
     fn parse(&self, input: I) -> Option<(O, I)>;
 }
 
-impl<'a, I, O, F> Parser<O, I> for F
+impl<I, O, F> Parser<O, I> for F
 where
-    F: Fn(I) -> Option<(O, I)> + 'a,
+    F: Fn(I) -> Option<(O, I)>,
 {
     fn parse(&self, i: I) -> Option<(O, I)> {
         self(i)
@@ -20,7 +23,7 @@ pub fn take_while(pred: impl Fn(char) -> bool, input: &str) -> (&str, &str) {
         if !pred(c) {
             break;
         }
-        i += 1
+        i += c.len_utf8()
     }
     (&input[..i], &input[i..])
 }
@@ -36,8 +39,11 @@ pub fn take_while1(pred: impl Fn(char) -> bool, input: &str) -> Option<(&str, &s
 
 pub fn take(length: usize, input: &str) -> Option<(&str, &str)> {
     let mut char_ind_iter = input.char_indices();
-    let (ci, _) = char_ind_iter.nth(length - 1)?;
-    let ci = char_ind_iter.next().map(|(ci, _)| ci).unwrap_or(ci + 1);
+    char_ind_iter.nth(length - 1)?;
+    let ci = char_ind_iter
+        .next()
+        .map(|(ci, _)| ci)
+        .unwrap_or(input.len());
     Some((&input[..ci], &input[ci..]))
 }
 
@@ -95,7 +101,7 @@ pub fn words(number: usize, input: &str) -> Option<(&str, &str)> {
         rest = &rest[start_next_word..];
         pos_words_end += start_next_word;
     }
-    let pos_word_end = rest.find(' ').unwrap_or_else(|| rest.len());
+    let pos_word_end = rest.find(' ').unwrap_or(rest.len());
     pos_words_end += pos_word_end;
     Some((&input[..pos_words_end], &input[pos_words_end..]))
 }
@@ -108,7 +114,10 @@ pub fn optional<'a, O>(parser: impl Parser<O, &'a str>, input: &'a str) -> (Opti
     }
 }
 
-pub fn many1<'a, O>(parser: impl Parser<O, &'a str>, mut input: &'a str) -> Option<(Vec<O>, &str)> {
+pub fn many1<'a, O>(
+    parser: impl Parser<O, &'a str>,
+    mut input: &'a str,
+) -> Option<(Vec<O>, &'a str)> {
     let mut collected = Vec::new();
     while let Some((res, rest)) = parser.parse(input) {
         collected.push(res);
@@ -126,11 +135,14 @@ pub fn either<'a, O>(
     parser1: impl Parser<O, &'a str>,
     parser2: impl Parser<O, &'a str>,
     input: &'a str,
-) -> Option<(O, &str)> {
+) -> Option<(O, &'a str)> {
     parser1.parse(input).or_else(|| parser2.parse(input))
 }
 
-pub fn recognize<'a, O>(parser: impl Parser<O, &'a str>, input: &'a str) -> Option<(&str, &str)> {
+pub fn recognize<'a, O>(
+    parser: impl Parser<O, &'a str>,
+    input: &'a str,
+) -> Option<(&'a str, &'a str)> {
     let (_, rest) = parser.parse(input)?;
     // Feels a little weird, but stolen from Nom, so probably fine, maybe
     let input_ptr = input.as_ptr();
@@ -150,12 +162,188 @@ pub fn eof(input: &str) -> Option<(&str, &str)> {
 pub fn endline_terminated<'a, O>(
     parser: impl Parser<O, &'a str>,
     input: &'a str,
-) -> Option<(O, &str)> {
+) -> Option<(O, &'a str)> {
     let (res, input) = parser.parse(input)?;
     let (_, input) = either(endline, eof, input)?;
     Some((res, input))
 }
 
+fn split_line(input: &str) -> (&str, &str) {
+    match input.find('\n') {
+        Some(end) => (&input[..end], &input[(end + 1)..]),
+        None => (input, ""),
+    }
+}
+
+// A line with something on it, without trailing whitespace (including \r) or the line break
+pub fn line(input: &str) -> Option<(&str, &str)> {
+    let (content, rest) = split_line(input);
+    let content = content.trim_end();
+    if content.is_empty() {
+        None
+    } else {
+        Some((content, rest))
+    }
+}
+
+pub fn blank_line(input: &str) -> Option<(&str, &str)> {
+    if input.is_empty() {
+        return None;
+    }
+    let (content, rest) = split_line(input);
+    if content.trim().is_empty() {
+        Some((&input[..(input.len() - rest.len())], rest))
+    } else {
+        None
+    }
+}
+
+fn skip_blank_lines(mut input: &str) -> &str {
+    while let Some((_, rest)) = blank_line(input) {
+        input = rest;
+    }
+    input
+}
+
+// A group of lines separated from the next by blank lines, with every line parsed
+// completely by the given parser
+pub fn record<'a, O>(parser: impl Parser<O, &'a str>, input: &'a str) -> Option<(Vec<O>, &'a str)> {
+    let mut input = skip_blank_lines(input);
+    let mut collected = Vec::new();
+    while let Some((content, rest)) = line(input) {
+        match parser.parse(content) {
+            Some((res, "")) => collected.push(res),
+            _ => return None,
+        }
+        input = rest;
+    }
+
+    if !collected.is_empty() {
+        Some((collected, skip_blank_lines(input)))
+    } else {
+        None
+    }
+}
+
+pub fn records<'a, O>(
+    parser: impl Parser<O, &'a str>,
+    input: &'a str,
+) -> Option<(Vec<Vec<O>>, &'a str)> {
+    many1(|inp| record(|l| parser.parse(l), inp), input)
+}
+
+fn whole(input: &str) -> Option<(&str, &str)> {
+    Some((input, &input[input.len()..]))
+}
+
+pub fn paragraph(input: &str) -> Option<(Vec<&str>, &str)> {
+    record(whole, input)
+}
+
+pub fn paragraphs(input: &str) -> Option<(Vec<Vec<&str>>, &str)> {
+    many1(paragraph, input)
+}
+
+pub fn key_value(input: &str) -> Option<((&str, &str), &str)> {
+    let (key, input) = take_while1(|c| !c.is_whitespace() && c != ':', input)?;
+    let (_, input) = fixed(":", input)?;
+    let (value, input) = take_while1(|c| !c.is_whitespace(), input)?;
+    Some(((key, value), input))
+}
+
+// Whitespace separated key:value pairs on a single line
+pub fn key_values(input: &str) -> Option<(Vec<(&str, &str)>, &str)> {
+    many1(
+        |inp| {
+            let (_, inp) = take_while(|c| c == ' ' || c == '\t', inp);
+            key_value(inp)
+        },
+        input,
+    )
+}
+
+pub fn key_value_record(input: &str) -> Option<(Vec<(&str, &str)>, &str)> {
+    let (lines, input) = record(key_values, input)?;
+    Some((lines.into_iter().flatten().collect(), input))
+}
+
+#[derive(Debug, PartialEq)]
+pub enum GridError {
+    Empty,
+    // Rows and columns are 1-based, like an editor would show them
+    UnexpectedChar {
+        row: usize,
+        column: usize,
+        found: char,
+    },
+    RaggedRow {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for GridError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GridError::Empty => write!(f, "empty grid"),
+            GridError::UnexpectedChar { row, column, found } => write!(
+                f,
+                "unexpected character {:?} at row {}, column {}",
+                found, row, column
+            ),
+            GridError::RaggedRow {
+                row,
+                expected,
+                found,
+            } => write!(f, "row {} is {} wide, expected {}", row, found, expected),
+        }
+    }
+}
+
+impl Error for GridError {}
+
+// Returns the tiles row by row along with the width. Accepts both \n and \r\n
+// line endings and ignores trailing line breaks.
+pub fn grid<T>(
+    input: &str,
+    tile: impl Fn(char) -> Option<T>,
+) -> Result<(Vec<T>, usize), GridError> {
+    let mut tiles = Vec::with_capacity(input.len());
+    let mut width = None;
+    for (row, line) in input
+        .trim_end_matches(&['\r', '\n'][..])
+        .lines()
+        .enumerate()
+    {
+        let mut row_width = 0;
+        for (column, c) in line.chars().enumerate() {
+            let t = tile(c).ok_or(GridError::UnexpectedChar {
+                row: row + 1,
+                column: column + 1,
+                found: c,
+            })?;
+            tiles.push(t);
+            row_width += 1;
+        }
+        match width {
+            None => width = Some(row_width),
+            Some(expected) if expected != row_width => {
+                return Err(GridError::RaggedRow {
+                    row: row + 1,
+                    expected,
+                    found: row_width,
+                })
+            }
+            _ => {}
+        }
+    }
+    match width {
+        Some(width) if width > 0 => Ok((tiles, width)),
+        _ => Err(GridError::Empty),
+    }
+}
+
 #[macro_export]
 macro_rules! make_parser {
     ($parser:path, $($arg:expr),*) => {
@@ -234,6 +422,19 @@ mod test {
         assert_eq!(res, Some(("1234", "")));
     }
 
+    #[test]
+    fn take_while_multibyte_chars() {
+        assert_eq!(take_while(|c| c != ' ', "éé b"), ("éé", " b"));
+        assert_eq!(take_while(|c| c != ' ', "aé"), ("aé", ""));
+    }
+
+    #[test]
+    fn take_ends_on_multibyte_char() {
+        assert_eq!(take(1, "é"), Some(("é", "")));
+        assert_eq!(take(3, "abé"), Some(("abé", "")));
+        assert_eq!(take(2, "éa"), Some(("éa", "")));
+    }
+
     #[test]
     fn fixed_matches() {
         let input = "1234abc";
@@ -312,11 +513,7 @@ mod test {
     }
 
     fn two_space(input: &str) -> Option<(&str, &str)> {
-        if input.starts_with("  ") {
-            Some((&input[..2], &input[2..]))
-        } else {
-            None
-        }
+        input.strip_prefix("  ").map(|rest| (&input[..2], rest))
     }
 
     #[test]
@@ -462,4 +659,113 @@ mod test {
         assert_eq!(any_parser("c "), Some(("c", " ")));
         assert!(any_parser("d ").is_none());
     }
+
+    fn digit(c: char) -> Option<u32> {
+        c.to_digit(10)
+    }
+
+    #[test]
+    fn grid_rows() {
+        let res = grid("12\n34", digit);
+        assert_eq!(res, Ok((vec![1, 2, 3, 4], 2)));
+    }
+
+    #[test]
+    fn grid_crlf_and_trailing_newlines() {
+        let res = grid("12\r\n34\r\n\r\n", digit);
+        assert_eq!(res, Ok((vec![1, 2, 3, 4], 2)));
+    }
+
+    #[test]
+    fn grid_unexpected_char() {
+        let res = grid("12\n3x", digit);
+        assert_eq!(
+            res,
+            Err(GridError::UnexpectedChar {
+                row: 2,
+                column: 2,
+                found: 'x'
+            })
+        );
+    }
+
+    #[test]
+    fn grid_ragged_row() {
+        let res = grid("12\n34\n5\n67", digit);
+        assert_eq!(
+            res,
+            Err(GridError::RaggedRow {
+                row: 3,
+                expected: 2,
+                found: 1
+            })
+        );
+        let res = grid("12\n\n34", digit);
+        assert_eq!(
+            res,
+            Err(GridError::RaggedRow {
+                row: 2,
+                expected: 2,
+                found: 0
+            })
+        );
+    }
+
+    #[test]
+    fn grid_empty() {
+        assert_eq!(grid("", digit), Err(GridError::Empty));
+        assert_eq!(grid("\n\n", digit), Err(GridError::Empty));
+    }
+
+    #[test]
+    fn line_trims_end() {
+        assert_eq!(line("abc \r\ndef"), Some(("abc", "def")));
+        assert_eq!(line("abc"), Some(("abc", "")));
+        assert!(line(" \r\ndef").is_none());
+        assert!(line("").is_none());
+    }
+
+    #[test]
+    fn blank_line_match() {
+        assert_eq!(blank_line(" \r\nabc"), Some((" \r\n", "abc")));
+        assert_eq!(blank_line("\t"), Some(("\t", "")));
+        assert!(blank_line("abc").is_none());
+        assert!(blank_line("").is_none());
+    }
+
+    #[test]
+    fn paragraph_lines() {
+        let input = "ab\nc\n\nd";
+        assert_eq!(paragraph(input), Some((vec!["ab", "c"], "d")));
+    }
+
+    #[test]
+    fn paragraphs_crlf_and_whitespace() {
+        let input = "\r\nab \r\nc\r\n \r\n\r\nd\r\n\r\n";
+        let res = paragraphs(input);
+        assert_eq!(res, Some((vec![vec!["ab", "c"], vec!["d"]], "")));
+        assert!(paragraphs("\n\n").is_none());
+    }
+
+    #[test]
+    fn records_parse_lines() {
+        let parser = |inp| take_while1(|c| c.is_ascii_digit(), inp);
+        let res = records(parser, "12\n3\n\n45\n");
+        assert_eq!(res, Some((vec![vec!["12", "3"], vec!["45"]], "")));
+        let res = records(parser, "12\n\n4a\n");
+        assert_eq!(res, Some((vec![vec!["12"]], "4a\n")));
+        assert!(record(parser, "1a").is_none());
+    }
+
+    #[test]
+    fn key_value_records() {
+        let input = "a:1 b:#2\nc:3\n\nd:4";
+        let res = key_value_record(input);
+        assert_eq!(
+            res,
+            Some((vec![("a", "1"), ("b", "#2"), ("c", "3")], "d:4"))
+        );
+        assert!(key_value("a 1").is_none());
+        assert!(key_value_record("a:1 b").is_none());
+    }
 }